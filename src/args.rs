@@ -0,0 +1,43 @@
+use crate::*;
+
+/// Applies the options of the command line: `--solver greedy|least-squares`,
+/// `--crop x,y[,zoom]` and `--rotate degrees`.
+pub fn apply_args(mut args: impl Iterator<Item = String>, settings: &mut Settings) {
+    while let Some(option) = args.next() {
+        let mut value = || args.next().unwrap_or_default();
+        match option.as_str() {
+            "--solver" => {
+                let value = value();
+                match SolverKind::from_name(&value) {
+                    Some(solver) => settings.solver = solver,
+                    None => error!("Expected --solver greedy|least-squares, got {}", value),
+                }
+            }
+            "--crop" => {
+                let value = value();
+                let values: Vec<f32> = value.split(',').filter_map(|v| v.parse().ok()).collect();
+                match values[..] {
+                    [x, y, zoom] => {
+                        settings.crop_x = x;
+                        settings.crop_y = y;
+                        settings.crop_zoom = zoom;
+                    }
+                    [x, y] => {
+                        settings.crop_x = x;
+                        settings.crop_y = y;
+                    }
+                    _ => error!("Expected --crop x,y[,zoom], got {}", value),
+                }
+            }
+            "--rotate" => {
+                let value = value();
+                match value.parse() {
+                    Ok(degrees) => settings.crop_rotation = degrees,
+                    Err(_) => error!("Expected --rotate degrees, got {}", value),
+                }
+            }
+            // Unknown options take no value, so the next argument is read as an option
+            _ => error!("Unknown option {}", option),
+        }
+    }
+}
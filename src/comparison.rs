@@ -0,0 +1,86 @@
+use crate::*;
+
+pub struct ComparisonPlugin;
+
+impl Plugin for ComparisonPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update_comparison_overlay);
+    }
+}
+
+/// Target drawn over the result image in the target, blink and wipe views.
+#[derive(Component)]
+struct ComparisonOverlay;
+
+/// Time each image stays on screen in the blink view, in seconds.
+const BLINK_INTERVAL: f32 = 0.5;
+
+const WIPE_EDGE_COLOR: [u8; 4] = [230, 40, 40, 255];
+
+pub fn spawn_comparison_overlay(parent: &mut ChildBuilder, assets: &mut Assets<Image>) {
+    parent.spawn((
+        ImageBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                ..default()
+            },
+            image: UiImage::new(assets.add(Image::default())),
+            ..default()
+        },
+        ComparisonOverlay,
+    ));
+}
+
+/// Keeps the target on the left of the wipe edge only.
+fn wipe(image: &mut Image, position: f32) {
+    let width = image.texture_descriptor.size.width as usize;
+    let edge = (position * width as f32).round() as usize;
+
+    for row in image.data.chunks_exact_mut(width * 4) {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            if x == edge.min(width - 1) {
+                pixel.copy_from_slice(&WIPE_EDGE_COLOR);
+            } else if x > edge {
+                pixel[3] = 0;
+            }
+        }
+    }
+}
+
+fn update_comparison_overlay(
+    trace_query: Query<(Ref<ProcessedImage>, Ref<ViewSettings>, &Settings)>,
+    mut overlay_query: Query<(&mut Style, &UiImage), With<ComparisonOverlay>>,
+    mut assets: ResMut<Assets<Image>>,
+    time: Res<Time>,
+) {
+    let Ok((target, view_settings, settings)) = trace_query.get_single() else {
+        return;
+    };
+
+    let shown = match view_settings.view_mode {
+        ViewMode::Target | ViewMode::Wipe => true,
+        ViewMode::Blink => (time.elapsed_seconds() / BLINK_INTERVAL) as u32 % 2 == 1,
+        ViewMode::Result | ViewMode::SideBySide => false,
+    };
+
+    for (mut style, image) in &mut overlay_query {
+        let display = if shown { Display::Flex } else { Display::None };
+        if style.display != display {
+            style.display = display;
+        }
+
+        if !target.is_changed() && !view_settings.is_changed() {
+            continue;
+        }
+        let (Some(target), Some(image)) = (&target.0, assets.get_mut(&image.texture)) else {
+            continue;
+        };
+        *image = target_image(target, settings);
+        if view_settings.view_mode == ViewMode::Wipe {
+            wipe(image, view_settings.wipe_position);
+        }
+    }
+}
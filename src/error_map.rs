@@ -0,0 +1,111 @@
+use crate::*;
+use bevy::render::render_resource::*;
+use image::Luma;
+
+pub struct ErrorMapPlugin;
+
+impl Plugin for ErrorMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update_error_map);
+    }
+}
+
+#[derive(Component)]
+struct ErrorMap;
+
+pub fn spawn_error_map(parent: &mut ChildBuilder, assets: &mut Assets<Image>) {
+    parent.spawn((
+        ImageBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                ..default()
+            },
+            image: UiImage::new(assets.add(Image::default())),
+            ..default()
+        },
+        ErrorMap,
+    ));
+}
+
+/// Signed error of each pixel of the canvas: red where it is darker than the target,
+/// blue where it is lighter, more opaque for larger errors relative to the largest one.
+/// Darker and lighter are measured on the board and thread colors, which may be inverted.
+fn error_map_image(coverage: &Coverage, target: &image::GrayImage, settings: &Settings) -> Image {
+    let shown_luminance = |transmittance: u8| {
+        let [r, g, b, _] = settings.composite(transmittance as f32 / 255.);
+        luminance([r, g, b])
+    };
+
+    let errors: Vec<i32> = target
+        .enumerate_pixels()
+        .map(|(x, y, target)| {
+            let pixel = coverage.pixel(x, y);
+            let error = settings.color_dist(*target, Luma([pixel]));
+            if shown_luminance(pixel) < shown_luminance(target.0[0]) {
+                -error
+            } else {
+                error
+            }
+        })
+        .collect();
+    let max_error = errors.iter().map(|e| e.abs()).max().unwrap_or(0).max(1) as f32;
+
+    let data = errors
+        .iter()
+        .flat_map(|&error| {
+            let alpha = (error.abs() as f32 / max_error * 255.).round() as u8;
+            if error < 0 {
+                [220, 30, 30, alpha]
+            } else {
+                [30, 80, 230, alpha]
+            }
+        })
+        .collect();
+
+    Image::new(
+        Extent3d {
+            width: target.width(),
+            height: target.height(),
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+fn update_error_map(
+    trace_query: Query<(&StringTrace, &ProcessedImage, &Settings, &ViewSettings)>,
+    mut map_query: Query<(&mut Style, &UiImage), With<ErrorMap>>,
+    mut assets: ResMut<Assets<Image>>,
+) {
+    let Ok((trace, target, settings, view_settings)) = trace_query.get_single() else {
+        return;
+    };
+
+    for (mut style, image) in &mut map_query {
+        let display = if view_settings.show_error {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        if style.display != display {
+            style.display = display;
+        }
+
+        let Some(target) = &target.0 else {
+            continue;
+        };
+        let coverage = trace.solver.coverage();
+        if !view_settings.show_error || coverage.width() != target.width() {
+            continue;
+        }
+
+        if let Some(image) = assets.get_mut(&image.texture) {
+            *image = error_map_image(coverage, target, settings);
+        }
+    }
+}
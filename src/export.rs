@@ -0,0 +1,194 @@
+use crate::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, Rgba, RgbaImage};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub struct ExportPlugin;
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AnimationExport>()
+            .add_systems(Update, (export_on_key, poll_animation_export));
+    }
+}
+
+const NAIL_USAGE_PATH: &str = "nail_usage.csv";
+const INSTRUCTIONS_PATH: &str = "instructions.csv";
+const ANIMATION_GIF_PATH: &str = "construction.gif";
+const ANIMATION_FRAMES_PATH: &str = "construction";
+
+/// Animation being rendered and saved in the background, with its path.
+#[derive(Resource, Default)]
+struct AnimationExport(Option<(&'static str, Task<io::Result<()>>)>);
+
+/// U: exports the usage of each nail
+/// I: exports the building instructions
+/// W: saves the weight map next to the source image
+/// A: exports the animation of the strings being added
+fn export_on_key(
+    keys: Res<Input<KeyCode>>,
+    query: Query<(&StringTrace, &Settings, &ViewSettings, &WeightMap)>,
+    mut animation_export: ResMut<AnimationExport>,
+) {
+    for (trace, settings, view_settings, weight_map) in &query {
+        if keys.just_pressed(KeyCode::U) && trace.is_current(settings) {
+            let usage = nail_usage(trace.solver.sequence(), settings.node_count());
+            report(NAIL_USAGE_PATH, export_nail_usage(NAIL_USAGE_PATH, &usage));
+        }
+        if keys.just_pressed(KeyCode::I) {
+            let result = export_instructions(INSTRUCTIONS_PATH, trace.solver.sequence(), settings);
+            report(INSTRUCTIONS_PATH, result);
+        }
+        if keys.just_pressed(KeyCode::W) {
+            if let Some(weights) = &weight_map.source {
+                let path = weight_map_path(IMAGES_PATHS[settings.source_image_index]);
+                let result = weights.save(&path).map_err(io::Error::other);
+                report(&path.to_string_lossy(), result);
+            }
+        }
+        if keys.just_pressed(KeyCode::A) && trace.is_current(settings) {
+            if animation_export.0.is_some() {
+                info!("The animation is still being exported");
+            } else {
+                let sequence = trace.solver.sequence();
+                animation_export.0 = Some(export_animation(sequence, settings, view_settings));
+            }
+        }
+    }
+}
+
+/// Starts rendering the animation on the async compute pool, as it takes
+/// seconds on a finished path.
+fn export_animation(
+    sequence: &[u16],
+    settings: &Settings,
+    view_settings: &ViewSettings,
+) -> (&'static str, Task<io::Result<()>>) {
+    let sequence = sequence.to_vec();
+    let settings = settings.clone();
+    let view_settings = view_settings.clone();
+    let pool = AsyncComputeTaskPool::get();
+    match view_settings.animation_format {
+        AnimationFormat::Gif => (
+            ANIMATION_GIF_PATH,
+            pool.spawn(async move {
+                export_animation_gif(ANIMATION_GIF_PATH, &sequence, &settings, &view_settings)
+            }),
+        ),
+        AnimationFormat::PngSequence => (
+            ANIMATION_FRAMES_PATH,
+            pool.spawn(async move {
+                export_animation_frames(ANIMATION_FRAMES_PATH, &sequence, &settings, &view_settings)
+            }),
+        ),
+    }
+}
+
+fn poll_animation_export(mut animation_export: ResMut<AnimationExport>) {
+    let Some((path, task)) = &mut animation_export.0 else {
+        return;
+    };
+    if let Some(result) = future::block_on(future::poll_once(task)) {
+        report(path, result);
+        animation_export.0 = None;
+    }
+}
+
+fn report(path: &str, result: io::Result<()>) {
+    match result {
+        Ok(()) => info!("Exported {}", path),
+        Err(err) => error!("Could not export {}: {}", path, err),
+    }
+}
+
+/// Nails to visit in order, with the direction the thread wraps around each one.
+fn export_instructions(path: &str, sequence: &[u16], settings: &Settings) -> io::Result<()> {
+    let mut csv = String::from("step,nail,wrap\n");
+    for (step, nail) in sequence.iter().enumerate() {
+        let wrap = settings.wrap_direction.at(step).label();
+        csv += &format!("{},{},{}\n", step, nail, wrap);
+    }
+    fs::write(path, csv)
+}
+
+fn image_error(err: image::ImageError) -> io::Error {
+    io::Error::other(err)
+}
+
+/// Frames of the strings of the path being added one after the other,
+/// `animation_strings_per_frame` at a time, from the empty board to the finished one.
+fn animation_frames<'a>(
+    sequence: &'a [u16],
+    settings: &Settings,
+    view_settings: &ViewSettings,
+) -> impl Iterator<Item = RgbaImage> + 'a {
+    let resolution = view_settings.animation_resolution.max(1);
+    let settings = Settings {
+        diameter: resolution,
+        ..settings.clone()
+    };
+    let mut coverage = Coverage::new(resolution, resolution, settings.supersampling);
+
+    let strings = sequence.len().saturating_sub(1);
+    let strings_per_frame = view_settings.animation_strings_per_frame.max(1) as usize;
+    let frames = strings.div_ceil(strings_per_frame);
+
+    (0..=frames).map(move |frame| {
+        let first = frame.saturating_sub(1) * strings_per_frame;
+        let last = (frame * strings_per_frame).min(strings);
+        for step in first..last {
+            settings.trace_string(sequence[step], sequence[step + 1], step, |point, alpha| {
+                coverage.add(point.0, point.1, string_density(alpha, &settings));
+            });
+        }
+
+        RgbaImage::from_fn(resolution, resolution, |x, y| {
+            Rgba(settings.composite(coverage.pixel(x, y) as f32 / 255.))
+        })
+    })
+}
+
+fn export_animation_gif(
+    path: &str,
+    sequence: &[u16],
+    settings: &Settings,
+    view_settings: &ViewSettings,
+) -> io::Result<()> {
+    let mut encoder = GifEncoder::new_with_speed(fs::File::create(path)?, 10);
+    encoder.set_repeat(Repeat::Infinite).map_err(image_error)?;
+
+    let delay = Delay::from_numer_denom_ms(1000, view_settings.animation_frame_rate.max(1));
+    for image in animation_frames(sequence, settings, view_settings) {
+        let frame = Frame::from_parts(image, 0, 0, delay);
+        encoder.encode_frame(frame).map_err(image_error)?;
+    }
+    Ok(())
+}
+
+/// Saves the frames as `frame_00000.png`, `frame_00001.png`... in a directory,
+/// to be assembled at `animation_frame_rate` frames per second.
+fn export_animation_frames(
+    path: &str,
+    sequence: &[u16],
+    settings: &Settings,
+    view_settings: &ViewSettings,
+) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+    for (index, image) in animation_frames(sequence, settings, view_settings).enumerate() {
+        let frame_path = Path::new(path).join(format!("frame_{:05}.png", index));
+        image.save(frame_path).map_err(image_error)?;
+    }
+    Ok(())
+}
+
+fn export_nail_usage(path: &str, usage: &[u32]) -> io::Result<()> {
+    let mut csv = String::from("nail,wraps\n");
+    for (nail, wraps) in usage.iter().enumerate() {
+        csv += &format!("{},{}\n", nail, wraps);
+    }
+    fs::write(path, csv)
+}
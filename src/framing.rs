@@ -0,0 +1,175 @@
+use crate::*;
+use bevy::input::mouse::MouseWheel;
+use bevy::render::render_resource::*;
+use bevy::ui::RelativeCursorPosition;
+use std::ops::RangeInclusive;
+
+pub struct FramingPlugin;
+
+impl Plugin for FramingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (frame_with_mouse, update_framing_overlay));
+    }
+}
+
+#[derive(Component)]
+struct FramingOverlay;
+
+/// Zoom factor of one step of the mouse wheel.
+const ZOOM_STEP: f32 = 1.1;
+/// Values of `crop_zoom` reachable with the mouse wheel and the slider.
+pub const CROP_ZOOM_RANGE: RangeInclusive<f32> = 0.5..=8.0;
+/// Rotation of one step of the mouse wheel, in degrees.
+const ROTATION_STEP: f32 = 2.;
+
+/// Spawns the overlay showing the framed target and the edge of the board,
+/// with a reminder of the mouse gestures.
+pub fn spawn_framing_overlay(parent: &mut ChildBuilder, assets: &mut Assets<Image>) {
+    parent
+        .spawn((
+            ImageBundle {
+                style: Style {
+                    display: Display::None,
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    ..default()
+                },
+                image: UiImage::new(assets.add(Image::default())),
+                ..default()
+            },
+            FramingOverlay,
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    "Drag to move, wheel to zoom, shift + wheel to rotate",
+                    TextStyle {
+                        font_size: 18.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(8.),
+                    left: Val::Px(8.),
+                    ..default()
+                })
+                .with_background_color(Color::rgba(0., 0., 0., 0.6)),
+            );
+        });
+}
+
+/// While framing, dragging the result image pans the source image,
+/// the mouse wheel zooms and, with shift held, rotates it.
+fn frame_with_mouse(
+    mut query: Query<(&mut Settings, &RelativeCursorPosition, &ViewSettings)>,
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    mut last_cursor: Local<Option<Vec2>>,
+) {
+    let scroll: f32 = wheel.iter().map(|event| event.y.signum()).sum();
+
+    for (mut settings, cursor, view_settings) in &mut query {
+        let active = view_settings.framing && cursor.mouse_over();
+        let dragging = active && buttons.pressed(MouseButton::Left);
+        let cursor = cursor.normalized.filter(|_| dragging);
+        let previous_cursor = std::mem::replace(&mut *last_cursor, cursor);
+
+        if !active {
+            continue;
+        }
+
+        if scroll != 0. {
+            if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+                settings.crop_rotation += scroll * ROTATION_STEP;
+            } else {
+                let zoom = settings.crop_zoom * ZOOM_STEP.powf(scroll);
+                settings.crop_zoom = zoom.clamp(*CROP_ZOOM_RANGE.start(), *CROP_ZOOM_RANGE.end());
+            }
+        }
+
+        let (Some(cursor), Some(previous_cursor)) = (cursor, previous_cursor) else {
+            continue;
+        };
+        let drag = cursor - previous_cursor;
+        if drag == Vec2::ZERO {
+            continue;
+        }
+        let Some(source_size) = source_image_size(&settings) else {
+            continue;
+        };
+
+        // The image follows the cursor: a drag across the whole frame moves it by the
+        // width of the frame, measured in pixels of the source image and rotated with it
+        let frame = drag * source_size.min_element() / settings.crop_zoom.max(0.1);
+        let (sin, cos) = settings.crop_rotation.to_radians().sin_cos();
+        let source = Vec2::new(frame.x * cos - frame.y * sin, frame.x * sin + frame.y * cos);
+        settings.crop_x -= source.x / source_size.x;
+        settings.crop_y -= source.y / source_size.y;
+    }
+}
+
+fn source_image_size(settings: &Settings) -> Option<Vec2> {
+    let (width, height) =
+        image::image_dimensions(IMAGES_PATHS[settings.source_image_index]).ok()?;
+    Some(Vec2::new(width as f32, height as f32))
+}
+
+/// The target, shaded outside of the board.
+fn overlay_image(target: &image::GrayImage, settings: &Settings) -> Image {
+    let size = target.width();
+    let center = size as f32 / 2.;
+
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+    for (x, y, pixel) in target.enumerate_pixels() {
+        let mut color = settings.composite(pixel.0[0] as f32 / 255.);
+        let distance = f32::hypot(x as f32 + 0.5 - center, y as f32 + 0.5 - center);
+        if distance > center {
+            for channel in &mut color[..3] {
+                *channel /= 3;
+            }
+        }
+        data.extend_from_slice(&color);
+    }
+
+    Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+fn update_framing_overlay(
+    trace_query: Query<(Ref<ProcessedImage>, Ref<ViewSettings>, &Settings)>,
+    mut overlay_query: Query<(&mut Style, &UiImage), With<FramingOverlay>>,
+    mut assets: ResMut<Assets<Image>>,
+) {
+    let Ok((target, view_settings, settings)) = trace_query.get_single() else {
+        return;
+    };
+    if !target.is_changed() && !view_settings.is_changed() {
+        return;
+    }
+
+    for (mut style, image) in &mut overlay_query {
+        style.display = if view_settings.framing {
+            Display::Flex
+        } else {
+            Display::None
+        };
+
+        if let (true, Some(target)) = (view_settings.framing, &target.0) {
+            if let Some(image) = assets.get_mut(&image.texture) {
+                *image = overlay_image(target, settings);
+            }
+        }
+    }
+}
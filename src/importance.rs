@@ -0,0 +1,174 @@
+use image::{imageops, GrayImage, Luma};
+use imageproc::filter::gaussian_blur_f32;
+use imageproc::gradients::sobel_gradients;
+use std::f32::consts::PI;
+
+/// Resolution the spectral residual is computed at, which must be a power of two.
+const SALIENCY_SIZE: u32 = 64;
+
+/// Weight map derived from the content of the image: edges and salient areas
+/// are weighted up to 1, and the rest down to `1 - strength`.
+pub fn importance_map(image: &GrayImage, strength: f32) -> GrayImage {
+    let edges = edge_strength(image);
+    let saliency = spectral_residual_saliency(image);
+
+    let mut weights = GrayImage::new(image.width(), image.height());
+    for ((weight, edge), salient) in weights
+        .pixels_mut()
+        .zip(edges.pixels())
+        .zip(saliency.pixels())
+    {
+        let importance = (edge.0[0] as f32 + salient.0[0] as f32) / 2.;
+        weight.0[0] = ((1. - strength) * 255. + strength * importance).round() as u8;
+    }
+    weights
+}
+
+/// Scales the values to fill the range of a grayscale image.
+fn normalize(width: u32, height: u32, values: &[f32]) -> GrayImage {
+    let max = values.iter().copied().fold(0., f32::max);
+    let scale = if max > 0. { 255. / max } else { 0. };
+    GrayImage::from_fn(width, height, |x, y| {
+        Luma([(values[(x + y * width) as usize] * scale).round() as u8])
+    })
+}
+
+/// Magnitude of the Sobel gradient, blurred so thin edges weigh their surroundings too.
+fn edge_strength(image: &GrayImage) -> GrayImage {
+    let gradients = sobel_gradients(image);
+    let values: Vec<f32> = gradients.pixels().map(|p| p.0[0] as f32).collect();
+    let edges = normalize(image.width(), image.height(), &values);
+    gaussian_blur_f32(&edges, image.width() as f32 / 200. + 1.)
+}
+
+/// Saliency from the spectral residual of the image (Hou and Zhang, 2007): the parts of
+/// the log amplitude spectrum that differ from its local average are the unexpected ones.
+fn spectral_residual_saliency(image: &GrayImage) -> GrayImage {
+    let size = SALIENCY_SIZE as usize;
+    let small = imageops::resize(
+        image,
+        SALIENCY_SIZE,
+        SALIENCY_SIZE,
+        imageops::FilterType::Triangle,
+    );
+
+    let mut spectrum: Vec<(f32, f32)> = small.pixels().map(|p| (p.0[0] as f32, 0.)).collect();
+    fft_2d(&mut spectrum, size, false);
+
+    let log_amplitude: Vec<f32> = spectrum
+        .iter()
+        .map(|&(re, im)| (f32::hypot(re, im) + 1e-6).ln())
+        .collect();
+
+    for (index, value) in spectrum.iter_mut().enumerate() {
+        let (x, y) = (index % size, index / size);
+        let mut sum = 0.;
+        let mut count = 0.;
+        for ny in y.saturating_sub(1)..=(y + 1).min(size - 1) {
+            for nx in x.saturating_sub(1)..=(x + 1).min(size - 1) {
+                sum += log_amplitude[nx + ny * size];
+                count += 1.;
+            }
+        }
+
+        let residual = (log_amplitude[index] - sum / count).exp();
+        let phase = value.1.atan2(value.0);
+        *value = (residual * phase.cos(), residual * phase.sin());
+    }
+    fft_2d(&mut spectrum, size, true);
+
+    let values: Vec<f32> = spectrum.iter().map(|&(re, im)| re * re + im * im).collect();
+    let saliency = gaussian_blur_f32(&normalize(SALIENCY_SIZE, SALIENCY_SIZE, &values), 2.);
+    imageops::resize(
+        &saliency,
+        image.width(),
+        image.height(),
+        imageops::FilterType::Triangle,
+    )
+}
+
+/// In-place radix-2 fast Fourier transform of complex numbers given as `(re, im)`.
+/// The length must be a power of two.
+fn fft(data: &mut [(f32, f32)], inverse: bool) {
+    let n = data.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1. } else { -1. };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2. * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (s, c) = (angle * k as f32).sin_cos();
+                let a = data[start + k];
+                let b = data[start + k + len / 2];
+                let t = (b.0 * c - b.1 * s, b.0 * s + b.1 * c);
+                data[start + k] = (a.0 + t.0, a.1 + t.1);
+                data[start + k + len / 2] = (a.0 - t.0, a.1 - t.1);
+            }
+        }
+        len <<= 1;
+    }
+
+    if inverse {
+        for value in data {
+            *value = (value.0 / n as f32, value.1 / n as f32);
+        }
+    }
+}
+
+/// Fourier transform of a square image, along the rows and then the columns.
+fn fft_2d(data: &mut [(f32, f32)], size: usize, inverse: bool) {
+    for row in data.chunks_exact_mut(size) {
+        fft(row, inverse);
+    }
+
+    let mut column = vec![(0., 0.); size];
+    for x in 0..size {
+        for (y, value) in column.iter_mut().enumerate() {
+            *value = data[x + y * size];
+        }
+        fft(&mut column, inverse);
+        for (y, value) in column.iter().enumerate() {
+            data[x + y * size] = *value;
+        }
+    }
+}
+
+#[test]
+fn fft_tests() {
+    let signal: Vec<(f32, f32)> = (0..8).map(|i| ((i * i) as f32, (8 - i) as f32)).collect();
+
+    let mut spectrum = signal.clone();
+    fft(&mut spectrum, false);
+
+    // Compare with the definition of the discrete Fourier transform
+    for (k, value) in spectrum.iter().enumerate() {
+        let mut expected = (0., 0.);
+        for (n, x) in signal.iter().enumerate() {
+            let (s, c) = (-2. * PI * (k * n) as f32 / 8.).sin_cos();
+            expected.0 += x.0 * c - x.1 * s;
+            expected.1 += x.0 * s + x.1 * c;
+        }
+        assert!((value.0 - expected.0).abs() < 1e-3);
+        assert!((value.1 - expected.1).abs() < 1e-3);
+    }
+
+    fft(&mut spectrum, true);
+    for (value, x) in spectrum.iter().zip(&signal) {
+        assert!((value.0 - x.0).abs() < 1e-3);
+        assert!((value.1 - x.1).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::*;
+use bevy::render::render_resource::*;
+use image::GrayImage;
+use std::path::{Path, PathBuf};
+
+pub struct KeepOutPlugin;
+
+impl Plugin for KeepOutPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (load_keep_out_mask, update_keep_out_overlay));
+    }
+}
+
+/// Mask of the areas of the board that strings may not cross, in white.
+#[derive(Component, Default)]
+pub struct KeepOutMask {
+    /// Mask framed like the target
+    pub image: Option<GrayImage>,
+    /// Mask aligned with the source image, as loaded
+    source: Option<GrayImage>,
+    framing: Option<Framing>,
+    source_image_index: Option<usize>,
+}
+
+#[derive(Component)]
+struct KeepOutOverlay;
+
+/// Path of the keep-out mask of a source image: `assets/a.jpg` uses `assets/a.keepout.png`.
+pub fn keep_out_mask_path(image_path: &str) -> PathBuf {
+    Path::new(image_path).with_extension("keepout.png")
+}
+
+/// Spawns the overlay showing the keep-out areas on top of the result image.
+pub fn spawn_keep_out_overlay(parent: &mut ChildBuilder, assets: &mut Assets<Image>) {
+    parent.spawn((
+        ImageBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                ..default()
+            },
+            image: UiImage::new(assets.add(overlay_image(&KeepOut::default()))),
+            ..default()
+        },
+        KeepOutOverlay,
+    ));
+}
+
+/// Loads the keep-out mask of the source image when another image is selected,
+/// and frames it again when the framing changes.
+fn load_keep_out_mask(mut query: Query<(&mut KeepOutMask, &Settings), Changed<Settings>>) {
+    for (mut mask, settings) in &mut query {
+        if mask.source_image_index != Some(settings.source_image_index) {
+            let path = keep_out_mask_path(IMAGES_PATHS[settings.source_image_index]);
+            let source = load_source_map(&path);
+            if source.is_some() {
+                info!("Loaded keep-out mask {}", path.display());
+            }
+
+            mask.source = source;
+            mask.source_image_index = Some(settings.source_image_index);
+        } else if mask.framing == Some(Framing::of(settings)) {
+            continue;
+        }
+
+        // Strings may cross the parts of the board outside the mask
+        mask.image = mask
+            .source
+            .as_ref()
+            .map(|map| frame_image(map, settings, 0));
+        mask.framing = Some(Framing::of(settings));
+    }
+}
+
+/// Hatched red over the keep-out areas.
+fn overlay_image(keep_out: &KeepOut) -> Image {
+    let size = keep_out.width().max(1);
+    let mut data = vec![0; (size * size * 4) as usize];
+    for y in 0..size {
+        for x in 0..size {
+            if keep_out.contains(x, y) {
+                let alpha = if (x + y) % 8 < 4 { 200 } else { 90 };
+                let index = 4 * (x + y * size) as usize;
+                data[index..index + 4].copy_from_slice(&[220, 30, 30, alpha]);
+            }
+        }
+    }
+    Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+#[allow(clippy::type_complexity)]
+fn update_keep_out_overlay(
+    trace_query: Query<(&KeepOutMask, &Settings), Or<(Changed<KeepOutMask>, Changed<Settings>)>>,
+    mut overlay_query: Query<(&mut Style, &UiImage), With<KeepOutOverlay>>,
+    mut assets: ResMut<Assets<Image>>,
+) {
+    let Ok((mask, settings)) = trace_query.get_single() else {
+        return;
+    };
+
+    let keep_out = KeepOut::new(mask.image.as_ref(), settings);
+    for (mut style, image) in &mut overlay_query {
+        style.display = if keep_out.is_empty() {
+            Display::None
+        } else {
+            Display::Flex
+        };
+
+        if let Some(image) = assets.get_mut(&image.texture) {
+            *image = overlay_image(&keep_out);
+        }
+    }
+}
@@ -1,15 +1,42 @@
+mod args;
+mod comparison;
+mod error_map;
+mod export;
+mod framing;
 mod image_handle;
+mod importance;
+mod keep_out_mask;
+mod nail_markers;
+mod nail_stats;
+mod playback;
 mod settings;
 mod source_image_processing;
 mod string_trace;
+mod tone;
 mod ui_widgets;
+mod weight_map;
+mod zoom;
 
+use args::*;
 use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+use comparison::*;
+use error_map::*;
+use export::*;
+use framing::*;
 use image_handle::*;
+use importance::*;
+use keep_out_mask::*;
+use nail_markers::*;
+use nail_stats::*;
+use playback::*;
 use settings::*;
 use source_image_processing::*;
 use string_trace::*;
+use tone::*;
 use ui_widgets::*;
+use weight_map::*;
+use zoom::*;
 
 fn main() {
     App::new()
@@ -19,7 +46,18 @@ fn main() {
             StringTracePlugin,
             UIWidgetsPlugin,
             SettingsPlugin,
+            NailStatsPlugin,
+            ExportPlugin,
+            WeightMapPlugin,
+            KeepOutPlugin,
+            FramingPlugin,
+            TonePlugin,
+            ComparisonPlugin,
+            ErrorMapPlugin,
+            ZoomPlugin,
+            NailMarkersPlugin,
         ))
+        .add_plugins(PlaybackPlugin)
         .add_systems(Startup, spawn)
         .run();
 }
@@ -27,7 +65,9 @@ fn main() {
 fn spawn(mut commands: Commands, mut assets: ResMut<Assets<Image>>) {
     commands.spawn(Camera2dBundle::default());
 
-    let settings = Settings::default();
+    let mut settings = Settings::default();
+    apply_args(std::env::args().skip(1), &mut settings);
+    let view_settings = ViewSettings::default();
     let string_trace = StringTrace::new(&settings, &mut assets);
     let result_texture = string_trace.canvas.clone();
 
@@ -44,26 +84,40 @@ fn spawn(mut commands: Commands, mut assets: ResMut<Assets<Image>>) {
             ..default()
         },))
         .with_children(|parent| {
-            settings_entity = parent
-                .spawn((
-                    ImageBundle {
-                        style: Style {
-                            width: Val::Auto,
-                            height: Val::Percent(100.),
+            parent.spawn(result_viewport()).with_children(|parent| {
+                settings_entity = parent
+                    .spawn((
+                        ImageBundle {
+                            style: result_style(),
+                            image: UiImage::new(result_texture),
                             ..default()
                         },
-                        image: UiImage::new(result_texture),
-                        ..default()
-                    },
-                    string_trace,
-                    ProcessedImage(None),
-                    settings,
-                ))
-                .id();
+                        string_trace,
+                        ProcessedImage(None),
+                        settings.clone(),
+                        view_settings.clone(),
+                        WeightMap::default(),
+                        KeepOutMask::default(),
+                        RelativeCursorPosition::default(),
+                        Playback::default(),
+                    ))
+                    .with_children(|parent| {
+                        spawn_comparison_overlay(parent, &mut assets);
+                        spawn_error_map(parent, &mut assets);
+                        spawn_keep_out_overlay(parent, &mut assets);
+                        spawn_weight_overlay(parent, &mut assets);
+                        spawn_nail_stats(parent, &mut assets);
+                        spawn_nail_markers(parent, &mut assets);
+                        spawn_playback_view(parent, &mut assets);
+                        spawn_framing_overlay(parent, &mut assets);
+                    })
+                    .id();
+            });
+            spawn_target_preview(parent, &mut assets);
         })
         .id();
 
-    let settings_ui = SettingsUI::new(&mut commands, settings_entity);
+    let settings_ui = SettingsUI::new(&mut commands, settings_entity, &settings, &view_settings);
     commands.add(AddChild {
         parent: page,
         child: settings_ui,
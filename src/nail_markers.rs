@@ -0,0 +1,185 @@
+use crate::*;
+
+pub struct NailMarkersPlugin;
+
+impl Plugin for NailMarkersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (update_nail_markers, update_nail_labels));
+    }
+}
+
+#[derive(Component)]
+struct NailMarkersView;
+
+#[derive(Component)]
+struct NailMarkers;
+
+#[derive(Component)]
+struct NailLabels;
+
+const NAIL_COLOR: [u8; 4] = [255, 190, 0, 255];
+pub const HIGHLIGHT_COLOR: [u8; 4] = [0, 230, 120, 255];
+
+/// Spawns the nails drawn on top of the result image, with their indices.
+pub fn spawn_nail_markers(parent: &mut ChildBuilder, assets: &mut Assets<Image>) {
+    let full_size = Style {
+        position_type: PositionType::Absolute,
+        width: Val::Percent(100.),
+        height: Val::Percent(100.),
+        ..default()
+    };
+
+    parent
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    ..full_size.clone()
+                },
+                ..default()
+            },
+            NailMarkersView,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                ImageBundle {
+                    style: full_size.clone(),
+                    image: UiImage::new(assets.add(Image::default())),
+                    ..default()
+                },
+                NailMarkers,
+            ));
+            parent.spawn((
+                NodeBundle {
+                    style: full_size,
+                    ..default()
+                },
+                NailLabels,
+            ));
+        });
+}
+
+/// Draws a line between two points of the target, `width` pixels wide.
+pub fn draw_chord(image: &mut Image, a: (f32, f32), b: (f32, f32), width: f32, color: [u8; 4]) {
+    let length = f32::hypot(b.0 - a.0, b.1 - a.1);
+    let steps = (length * 2.).ceil().max(1.) as u32;
+    for step in 0..=steps {
+        let t = step as f32 / steps as f32;
+        let point = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+        draw_dot(image, point, width / 2., color);
+    }
+}
+
+/// Radius of the nail dots, in pixels of the target.
+pub fn marker_radius(settings: &Settings) -> f32 {
+    (settings.diameter as f32 / 300.).max(1.)
+}
+
+fn draw_nail_markers(image: &mut Image, sequence: &[u16], settings: &Settings) {
+    *image = transparent_image(settings.diameter, settings.diameter);
+
+    let radius = marker_radius(settings);
+    for node in 0..settings.node_count() {
+        draw_dot(image, settings.node_pos(node), radius, NAIL_COLOR);
+    }
+
+    if let [.., from, to] = sequence {
+        let step = sequence.len() - 2;
+        let (a, b) = settings.string_ends(*from, *to, step);
+        draw_chord(image, a, b, radius, HIGHLIGHT_COLOR);
+    }
+    if let Some(&current) = sequence.last() {
+        draw_dot(
+            image,
+            settings.node_pos(current),
+            radius * 2.5,
+            HIGHLIGHT_COLOR,
+        );
+    }
+}
+
+fn update_nail_markers(
+    trace_query: Query<(&StringTrace, &Settings, &ViewSettings)>,
+    mut view_query: Query<&mut Style, With<NailMarkersView>>,
+    markers_query: Query<&UiImage, With<NailMarkers>>,
+    mut assets: ResMut<Assets<Image>>,
+) {
+    let Ok((trace, settings, view_settings)) = trace_query.get_single() else {
+        return;
+    };
+
+    for mut style in &mut view_query {
+        let display = if view_settings.show_nails {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        if style.display != display {
+            style.display = display;
+        }
+    }
+
+    if !view_settings.show_nails || !trace.is_current(settings) {
+        return;
+    }
+
+    for image in &markers_query {
+        if let Some(image) = assets.get_mut(&image.texture) {
+            draw_nail_markers(image, trace.solver.sequence(), settings);
+        }
+    }
+}
+
+/// Labels every `nail_label_interval`th nail with its index, outside of the ring.
+fn update_nail_labels(
+    mut commands: Commands,
+    trace_query: Query<(Ref<Settings>, Ref<ViewSettings>)>,
+    labels_query: Query<Entity, With<NailLabels>>,
+) {
+    let Ok((settings, view_settings)) = trace_query.get_single() else {
+        return;
+    };
+    if !settings.is_changed() && !view_settings.is_changed() {
+        return;
+    }
+
+    for labels in &labels_query {
+        let mut labels = commands.entity(labels);
+        labels.despawn_descendants();
+
+        let interval = view_settings.nail_label_interval;
+        if !view_settings.show_nails || interval == 0 {
+            continue;
+        }
+
+        let size = settings.diameter as f32;
+        let center = size / 2.;
+        labels.with_children(|parent| {
+            for node in (0..settings.node_count()).step_by(interval as usize) {
+                let (x, y) = settings.node_pos(node);
+                // Pushed outwards so the label does not hide the nail
+                let (dx, dy) = (x - center, y - center);
+                let distance = f32::hypot(dx, dy).max(1.);
+                let shift = size * 0.02 / distance;
+                let (x, y) = (x + dx * shift, y + dy * shift);
+
+                parent.spawn(
+                    TextBundle::from_section(
+                        node.to_string(),
+                        TextStyle {
+                            font_size: 14.0,
+                            color: Color::WHITE,
+                            ..default()
+                        },
+                    )
+                    .with_style(Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Percent(x / size * 100.),
+                        top: Val::Percent(y / size * 100.),
+                        ..default()
+                    }),
+                );
+            }
+        });
+    }
+}
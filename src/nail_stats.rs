@@ -0,0 +1,233 @@
+use crate::*;
+use bevy::render::render_resource::*;
+
+pub struct NailStatsPlugin;
+
+impl Plugin for NailStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update_nail_stats);
+    }
+}
+
+#[derive(Component)]
+struct NailStatsView;
+
+#[derive(Component)]
+struct NailHeatMap;
+
+#[derive(Component)]
+struct NailHistogram;
+
+#[derive(Component)]
+struct NailStatsText;
+
+const HISTOGRAM_HEIGHT: u32 = 64;
+
+/// Number of times the path wraps around each nail.
+pub fn nail_usage(sequence: &[u16], nodes: u16) -> Vec<u32> {
+    let mut usage = vec![0; nodes as usize];
+    for &node in sequence {
+        usage[node as usize] += 1;
+    }
+    usage
+}
+
+/// Spawns the nail statistics on top of the result image:
+/// a ring of nails colored by usage, a histogram and a summary.
+pub fn spawn_nail_stats(parent: &mut ChildBuilder, assets: &mut Assets<Image>) {
+    let full_size = Style {
+        position_type: PositionType::Absolute,
+        width: Val::Percent(100.),
+        height: Val::Percent(100.),
+        ..default()
+    };
+
+    parent
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    ..full_size.clone()
+                },
+                ..default()
+            },
+            NailStatsView,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                ImageBundle {
+                    style: full_size.clone(),
+                    image: UiImage::new(assets.add(transparent_image(1, 1))),
+                    ..default()
+                },
+                NailHeatMap,
+            ));
+            parent.spawn((
+                ImageBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        bottom: Val::Px(0.),
+                        width: Val::Percent(100.),
+                        height: Val::Percent(15.),
+                        ..default()
+                    },
+                    image: UiImage::new(assets.add(transparent_image(1, 1))),
+                    ..default()
+                },
+                NailHistogram,
+            ));
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 18.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(4.),
+                    left: Val::Px(4.),
+                    ..default()
+                }),
+                NailStatsText,
+            ));
+        });
+}
+
+pub fn transparent_image(width: u32, height: u32) -> Image {
+    Image::new_fill(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Clears the image, resizing it if needed.
+fn clear_image(image: &mut Image, width: u32, height: u32, color: [u8; 4]) {
+    let size = image.texture_descriptor.size;
+    if size.width != width || size.height != height {
+        *image = transparent_image(width, height);
+    }
+    for pixel in image.data.chunks_exact_mut(4) {
+        pixel.copy_from_slice(&color);
+    }
+}
+
+fn set_pixel(image: &mut Image, x: i32, y: i32, color: [u8; 4]) {
+    let size = image.texture_descriptor.size;
+    if x < 0 || y < 0 || x as u32 >= size.width || y as u32 >= size.height {
+        return;
+    }
+    let index = 4 * (x as u32 + y as u32 * size.width) as usize;
+    image.data[index..index + 4].copy_from_slice(&color);
+}
+
+/// Draws a disc centered on a point of the image, clipped to the image.
+pub fn draw_dot(image: &mut Image, (cx, cy): (f32, f32), radius: f32, color: [u8; 4]) {
+    let reach = radius.ceil() as i32;
+    for dy in -reach..=reach {
+        for dx in -reach..=reach {
+            let (x, y) = (cx.floor() as i32 + dx, cy.floor() as i32 + dy);
+            let distance = f32::hypot(x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+            if distance <= radius {
+                set_pixel(image, x, y, color);
+            }
+        }
+    }
+}
+
+/// Blue for the least used nails to red for the most used ones.
+fn heat_color(t: f32) -> [u8; 4] {
+    let t = t.clamp(0., 1.);
+    let r = (t * 2.).min(1.);
+    let g = 1. - (t - 0.5).abs() * 2.;
+    let b = ((1. - t) * 2.).min(1.);
+    [(r * 255.) as u8, (g * 255.) as u8, (b * 255.) as u8, 255]
+}
+
+fn draw_heat_map(image: &mut Image, usage: &[u32], max_usage: u32, settings: &Settings) {
+    let size = settings.diameter;
+    clear_image(image, size, size, [0, 0, 0, 0]);
+
+    let radius = (size as f32 / 150.).max(2.);
+    for (node, &count) in usage.iter().enumerate() {
+        let color = heat_color(count as f32 / max_usage as f32);
+        draw_dot(image, settings.node_pos(node as u16), radius, color);
+    }
+}
+
+fn draw_histogram(image: &mut Image, usage: &[u32], max_usage: u32) {
+    let width = usage.len() as u32;
+    clear_image(image, width, HISTOGRAM_HEIGHT, [0, 0, 0, 160]);
+
+    for (x, &count) in usage.iter().enumerate() {
+        let t = count as f32 / max_usage as f32;
+        let color = heat_color(t);
+        let bar_height = (t * HISTOGRAM_HEIGHT as f32).round() as u32;
+
+        for y in HISTOGRAM_HEIGHT - bar_height..HISTOGRAM_HEIGHT {
+            let index = 4 * (x as u32 + y * width) as usize;
+            image.data[index..index + 4].copy_from_slice(&color);
+        }
+    }
+}
+
+fn update_nail_stats(
+    trace_query: Query<(&StringTrace, &Settings, &ViewSettings)>,
+    mut view_query: Query<&mut Style, With<NailStatsView>>,
+    heat_map_query: Query<&UiImage, With<NailHeatMap>>,
+    histogram_query: Query<&UiImage, With<NailHistogram>>,
+    mut text_query: Query<&mut Text, With<NailStatsText>>,
+    mut assets: ResMut<Assets<Image>>,
+) {
+    let Ok((trace, settings, view_settings)) = trace_query.get_single() else {
+        return;
+    };
+
+    for mut style in &mut view_query {
+        style.display = if view_settings.show_nail_stats {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+
+    if !view_settings.show_nail_stats || !trace.is_current(settings) {
+        return;
+    }
+
+    let usage = nail_usage(trace.solver.sequence(), settings.node_count());
+    let max_usage = usage.iter().copied().max().unwrap_or(0).max(1);
+
+    for image in &heat_map_query {
+        if let Some(image) = assets.get_mut(&image.texture) {
+            draw_heat_map(image, &usage, max_usage, settings);
+        }
+    }
+
+    for image in &histogram_query {
+        if let Some(image) = assets.get_mut(&image.texture) {
+            draw_histogram(image, &usage, max_usage);
+        }
+    }
+
+    let busiest = (0..usage.len())
+        .max_by_key(|&node| usage[node])
+        .unwrap_or(0);
+    let mean = usage.iter().sum::<u32>() as f32 / usage.len().max(1) as f32;
+    for mut text in &mut text_query {
+        text.sections[0].value = format!(
+            "max {} wraps (nail {}), mean {:.1}",
+            usage.get(busiest).copied().unwrap_or(0),
+            busiest,
+            mean
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use crate::*;
+use std::fs;
+
+pub struct PlaybackPlugin;
+
+impl Plugin for PlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (step_playback, update_playback_view).chain());
+    }
+}
+
+/// File keeping the position of the playback in each sequence between sessions,
+/// one `hash step` line per sequence.
+const PLAYBACK_PATH: &str = "playback_position.txt";
+
+/// Position in the path while following it to build the board.
+#[derive(Component, Default)]
+pub struct Playback {
+    /// Index of the string being wound, from `sequence[step]` to `sequence[step + 1]`
+    pub step: usize,
+    /// Hash of the sequence the step is in, once the trace is done
+    sequence_hash: Option<u64>,
+    /// Time since the last step, for the automatic advance
+    elapsed: f32,
+}
+
+/// FNV-1a hash of a sequence, which stays the same across sessions and builds.
+fn sequence_hash(sequence: &[u16]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in sequence.iter().flat_map(|node| node.to_le_bytes()) {
+        hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn load_positions() -> Vec<(u64, usize)> {
+    let text = fs::read_to_string(PLAYBACK_PATH).unwrap_or_default();
+    text.lines()
+        .filter_map(|line| {
+            let (hash, step) = line.split_once(' ')?;
+            Some((u64::from_str_radix(hash, 16).ok()?, step.parse().ok()?))
+        })
+        .collect()
+}
+
+impl Playback {
+    /// Resumes from the position saved for a sequence, or starts it from the beginning.
+    fn load(&mut self, sequence_hash: u64) {
+        let saved = load_positions()
+            .into_iter()
+            .find(|&(hash, _)| hash == sequence_hash);
+        self.step = saved.map_or(0, |(_, step)| step);
+        self.sequence_hash = Some(sequence_hash);
+        self.elapsed = 0.;
+    }
+
+    fn save(&self) {
+        let Some(sequence_hash) = self.sequence_hash else {
+            return;
+        };
+
+        let mut positions = load_positions();
+        positions.retain(|&(hash, _)| hash != sequence_hash);
+        positions.push((sequence_hash, self.step));
+        let text: String = positions
+            .iter()
+            .map(|(hash, step)| format!("{:016x} {}\n", hash, step))
+            .collect();
+
+        if let Err(err) = fs::write(PLAYBACK_PATH, text) {
+            error!("Could not save {}: {}", PLAYBACK_PATH, err);
+        }
+    }
+}
+
+#[derive(Component)]
+struct PlaybackView;
+
+#[derive(Component)]
+struct PlaybackChord;
+
+#[derive(Component)]
+struct PlaybackText;
+
+pub fn spawn_playback_view(parent: &mut ChildBuilder, assets: &mut Assets<Image>) {
+    let full_size = Style {
+        position_type: PositionType::Absolute,
+        width: Val::Percent(100.),
+        height: Val::Percent(100.),
+        ..default()
+    };
+
+    parent
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    ..full_size.clone()
+                },
+                ..default()
+            },
+            PlaybackView,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                ImageBundle {
+                    style: full_size,
+                    image: UiImage::new(assets.add(Image::default())),
+                    ..default()
+                },
+                PlaybackChord,
+            ));
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 48.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(8.),
+                    left: Val::Px(8.),
+                    ..default()
+                })
+                .with_background_color(Color::rgba(0., 0., 0., 0.6)),
+                PlaybackText,
+            ));
+        });
+}
+
+/// While playing back a finished trace, the right arrow or space goes to the next string
+/// and the left arrow to the previous one. With a pace set, the strings also advance on
+/// their own. The position is saved for the sequence on every step.
+fn step_playback(
+    mut query: Query<(&mut Playback, &StringTrace, &ViewSettings)>,
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time>,
+) {
+    for (mut playback, trace, view_settings) in &mut query {
+        if !view_settings.playback {
+            continue;
+        }
+        // Waits for the final sequence, without touching the saved positions
+        if !trace.solver.is_done() {
+            if playback.sequence_hash.is_some() {
+                playback.sequence_hash = None;
+            }
+            continue;
+        }
+
+        let sequence = trace.solver.sequence();
+        let hash = sequence_hash(sequence);
+        if playback.sequence_hash != Some(hash) {
+            playback.load(hash);
+        }
+
+        let last_step = sequence.len().saturating_sub(2);
+        let mut step = playback.step;
+
+        if keys.just_pressed(KeyCode::Right) || keys.just_pressed(KeyCode::Space) {
+            step = (step + 1).min(last_step);
+        }
+        if keys.just_pressed(KeyCode::Left) {
+            step = step.min(last_step).saturating_sub(1);
+        }
+
+        if view_settings.playback_pace > 0. {
+            playback.elapsed += time.delta_seconds();
+            if playback.elapsed >= view_settings.playback_pace {
+                playback.elapsed = 0.;
+                step = (step + 1).min(last_step);
+            }
+        }
+
+        if step != playback.step {
+            playback.step = step;
+            playback.elapsed = 0.;
+            playback.save();
+        }
+    }
+}
+
+fn update_playback_view(
+    trace_query: Query<(&StringTrace, Ref<Playback>, Ref<ViewSettings>, &Settings)>,
+    mut view_query: Query<&mut Style, With<PlaybackView>>,
+    chord_query: Query<&UiImage, With<PlaybackChord>>,
+    mut text_query: Query<&mut Text, With<PlaybackText>>,
+    mut assets: ResMut<Assets<Image>>,
+) {
+    let Ok((trace, playback, view_settings, settings)) = trace_query.get_single() else {
+        return;
+    };
+    if !playback.is_changed() && !view_settings.is_changed() {
+        return;
+    }
+
+    for mut style in &mut view_query {
+        style.display = if view_settings.playback {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+    if !view_settings.playback {
+        return;
+    }
+
+    // The saved step is only clamped for display, so a shorter sequence does not lose it
+    let sequence = trace.solver.sequence();
+    let step = playback.step.min(sequence.len().saturating_sub(2));
+    let ready = playback.sequence_hash.is_some()
+        && trace.solver.is_done()
+        && trace.is_current(settings)
+        && step + 1 < sequence.len();
+
+    for image in &chord_query {
+        if let Some(image) = assets.get_mut(&image.texture) {
+            *image = transparent_image(settings.diameter, settings.diameter);
+            if ready {
+                let (from, to) = (sequence[step], sequence[step + 1]);
+                let radius = marker_radius(settings);
+                let (a, b) = settings.string_ends(from, to, step);
+                draw_chord(image, a, b, radius * 1.5, HIGHLIGHT_COLOR);
+                draw_dot(image, settings.node_pos(to), radius * 2.5, HIGHLIGHT_COLOR);
+            }
+        }
+    }
+
+    let wrap = settings.wrap_direction.at(step + 1).label();
+    for mut text in &mut text_query {
+        text.sections[0].value = if ready {
+            format!(
+                "{} → {}\nstring {} / {}, {}",
+                sequence[step],
+                sequence[step + 1],
+                step + 1,
+                sequence.len() - 1,
+                wrap
+            )
+        } else {
+            "waiting for the trace to finish".to_string()
+        };
+    }
+}
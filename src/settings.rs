@@ -1,22 +1,264 @@
 use crate::*;
+use bevy::input::mouse::MouseWheel;
+use bevy::ui::RelativeCursorPosition;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 
+#[derive(Clone, Copy, PartialEq)]
 pub enum ColorDistanceFn {
     ABS,
     SQUARE,
 }
 
-#[derive(Component)]
+/// Algorithm that draws the threads on the canvas.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rasterizer {
+    XiaolinWu,
+    Gaussian,
+    Box,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WrapDirection {
+    Alternate,
+    Clockwise,
+    CounterClockwise,
+}
+
+impl WrapDirection {
+    /// Direction the thread wraps around the nail at a position of the path.
+    pub fn at(&self, step: usize) -> WrapDirection {
+        match self {
+            WrapDirection::Alternate if step.is_multiple_of(2) => WrapDirection::Clockwise,
+            WrapDirection::Alternate => WrapDirection::CounterClockwise,
+            direction => *direction,
+        }
+    }
+
+    /// Name of the direction in the instructions.
+    pub fn label(&self) -> &'static str {
+        match self {
+            WrapDirection::Alternate => "alternate",
+            WrapDirection::Clockwise => "clockwise",
+            WrapDirection::CounterClockwise => "counter-clockwise",
+        }
+    }
+}
+
+/// Colors available for the board and the thread.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorPreset {
+    White,
+    Black,
+    Walnut,
+    Gold,
+    Red,
+    Navy,
+}
+
+impl ColorPreset {
+    pub fn rgb(&self) -> [u8; 3] {
+        match self {
+            ColorPreset::White => [255, 255, 255],
+            ColorPreset::Black => [0, 0, 0],
+            ColorPreset::Walnut => [92, 64, 44],
+            ColorPreset::Gold => [212, 175, 55],
+            ColorPreset::Red => [178, 34, 34],
+            ColorPreset::Navy => [24, 36, 82],
+        }
+    }
+}
+
+/// Histogram equalization of the target.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Equalization {
+    Off,
+    Histogram,
+    /// Contrast limited, on tiles of the image
+    Adaptive,
+}
+
+/// Order of the colors on the sliders, starting with the default one.
+const BOARD_COLORS: [ColorPreset; 6] = [
+    ColorPreset::White,
+    ColorPreset::Black,
+    ColorPreset::Walnut,
+    ColorPreset::Navy,
+    ColorPreset::Red,
+    ColorPreset::Gold,
+];
+const THREAD_COLORS: [ColorPreset; 6] = [
+    ColorPreset::Black,
+    ColorPreset::White,
+    ColorPreset::Gold,
+    ColorPreset::Red,
+    ColorPreset::Navy,
+    ColorPreset::Walnut,
+];
+
+/// Nails placed evenly on a circle. Positions and radius are fractions of the
+/// radius of the board, from its center.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct NailRing {
+    pub center: (f32, f32),
+    pub radius: f32,
+    pub nails: u16,
+}
+
+/// Which sets of nails strings may join. The sets are the concentric rings,
+/// from the outer one inwards, followed by the clusters.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RingConnections {
+    All,
+    /// Every string has at least one end on the outer ring
+    ToOuter,
+    /// Strings stay within a set or join consecutive sets
+    Neighbours,
+}
+
+#[derive(Component, Clone)]
 pub struct Settings {
     pub diameter: u32,
     pub darken: u8,
+    /// Input values mapped to black and white
+    pub black_point: u8,
+    pub white_point: u8,
+    /// Added to the value of every pixel, from -1 to 1
+    pub brightness: f32,
+    pub contrast: f32,
+    pub gamma: f32,
+    pub equalization: Equalization,
+    /// Limit of the adaptive equalization, as a multiple of the mean of the histograms
+    pub clahe_clip_limit: f32,
+    pub sharpen_amount: f32,
+    /// Radius of the blur of the unsharp mask, in pixels
+    pub sharpen_radius: f32,
+    /// Fits the tones of the target to the darkness the strings can reach
+    pub auto_tone: bool,
     pub source_image_index: usize,
 
     pub nodes: u16,
     pub nodes_offset: f32,
+    /// Opacity of the thread
     pub string_alpha: f32,
     pub max_lines: usize,
     pub repeat_lines: bool,
     pub color_distance_fn: ColorDistanceFn,
+    pub beam_width: usize,
+    pub lookahead: usize,
+    pub refine_iterations: usize,
+    pub refine_temperature: f32,
+    pub solver: SolverKind,
+    pub lsq_iterations: usize,
+    pub seed: u64,
+    pub sample_top_k: usize,
+    pub sample_temperature: f32,
+    pub min_nail_skip: u16,
+    pub max_wraps_per_nail: u32,
+    pub nail_diameter_mm: f32,
+    pub board_diameter_mm: f32,
+    pub thread_thickness_mm: f32,
+    pub wrap_direction: WrapDirection,
+    pub rasterizer: Rasterizer,
+    /// Resolution of the canvas the strings are drawn on, as a multiple of the target resolution
+    pub supersampling: u32,
+    /// Weight of the edges and salient areas of the source image in the error,
+    /// when it has no hand-made weight map
+    pub importance_strength: f32,
+    /// Diameter of a hole in the center of the board that strings may not cross
+    pub keep_out_diameter_mm: f32,
+    /// Number of concentric rings of nails, including the outer one of `nodes` nails
+    pub rings: u16,
+    /// Distance between concentric rings, as a fraction of the radius of the board
+    pub ring_spacing: f32,
+    pub ring_connections: RingConnections,
+    /// Sets of nails inside the board, loaded with the source image
+    pub clusters: Vec<NailRing>,
+    pub board_color: ColorPreset,
+    pub thread_color: ColorPreset,
+    /// Offset of the center of the board from the center of the source image,
+    /// as fractions of its width and height
+    pub crop_x: f32,
+    pub crop_y: f32,
+    /// 1 when the board fits in the source image, larger to magnify it
+    pub crop_zoom: f32,
+    /// Rotation of the source image under the board, in degrees
+    pub crop_rotation: f32,
+}
+
+/// How the result is compared with the processed target.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ViewMode {
+    #[default]
+    Result,
+    Target,
+    SideBySide,
+    /// Alternates between the result and the target
+    Blink,
+    /// Target on the left of `wipe_position`, result on the right
+    Wipe,
+}
+
+/// File format of the exported construction animation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AnimationFormat {
+    #[default]
+    Gif,
+    /// Numbered PNG images, one per frame
+    PngSequence,
+}
+
+/// Settings that only change how the result is displayed,
+/// so they do not restart the trace.
+#[derive(Component, Clone)]
+pub struct ViewSettings {
+    pub show_nail_stats: bool,
+    /// Shows the weight map, which can then be painted on the result image
+    pub show_weights: bool,
+    /// Shows the framed target, which can then be moved on the result image
+    pub framing: bool,
+    pub view_mode: ViewMode,
+    /// Position of the edge between the target and the result in the wipe mode,
+    /// as a fraction of the width
+    pub wipe_position: f32,
+    /// Shows where the result is too dark (red) or too light (blue)
+    pub show_error: bool,
+    /// Shows the nails, the current one and the last string
+    pub show_nails: bool,
+    /// Labels every Nth nail with its index, none when 0
+    pub nail_label_interval: u16,
+    /// Shows the strings one at a time, to follow the path while building the board
+    pub playback: bool,
+    /// Seconds between strings in the playback, which only advances on key presses when 0
+    pub playback_pace: f32,
+    pub animation_format: AnimationFormat,
+    /// Strings added on each frame of the exported animation
+    pub animation_strings_per_frame: u32,
+    pub animation_frame_rate: u32,
+    /// Width and height of the exported animation, in pixels
+    pub animation_resolution: u32,
+}
+
+impl Default for ViewSettings {
+    fn default() -> Self {
+        Self {
+            show_nail_stats: false,
+            show_weights: false,
+            framing: false,
+            view_mode: ViewMode::Result,
+            wipe_position: 0.5,
+            show_error: false,
+            show_nails: false,
+            nail_label_interval: 10,
+            playback: false,
+            playback_pace: 0.,
+            animation_format: AnimationFormat::Gif,
+            animation_strings_per_frame: 20,
+            animation_frame_rate: 25,
+            animation_resolution: 500,
+        }
+    }
 }
 
 pub const IMAGES_PATHS: &[&'static str] = &[
@@ -35,14 +277,53 @@ impl Default for Settings {
         Self {
             diameter: 500,
             darken: 100,
+            black_point: 0,
+            white_point: 255,
+            brightness: 0.,
+            contrast: 1.,
+            gamma: 1.,
+            equalization: Equalization::Off,
+            clahe_clip_limit: 2.,
+            sharpen_amount: 0.,
+            sharpen_radius: 2.,
+            auto_tone: false,
             source_image_index: 0,
 
             nodes: 200,
             nodes_offset: 1.,
-            string_alpha: 0.3,
+            string_alpha: 1.,
             max_lines: 6000,
             repeat_lines: false,
             color_distance_fn: ColorDistanceFn::SQUARE,
+            beam_width: 1,
+            lookahead: 1,
+            refine_iterations: 0,
+            refine_temperature: 0.,
+            solver: SolverKind::Greedy,
+            lsq_iterations: 300,
+            seed: 0,
+            sample_top_k: 1,
+            sample_temperature: 0.1,
+            min_nail_skip: 1,
+            max_wraps_per_nail: 0,
+            nail_diameter_mm: 0.,
+            board_diameter_mm: 500.,
+            thread_thickness_mm: 0.3,
+            wrap_direction: WrapDirection::Alternate,
+            rasterizer: Rasterizer::XiaolinWu,
+            supersampling: 1,
+            importance_strength: 0.,
+            keep_out_diameter_mm: 0.,
+            rings: 1,
+            ring_spacing: 0.2,
+            ring_connections: RingConnections::All,
+            clusters: vec![],
+            board_color: ColorPreset::White,
+            thread_color: ColorPreset::Black,
+            crop_x: 0.,
+            crop_y: 0.,
+            crop_zoom: 1.,
+            crop_rotation: 0.,
         }
     }
 }
@@ -52,76 +333,145 @@ pub struct SettingsUI {
     node: NodeBundle,
 }
 
+/// Column of sliders, scrolled with the mouse wheel when it is taller than the panel.
+#[derive(Component, Default)]
+struct SettingsList {
+    /// Offset of the top of the column, in pixels
+    scroll: f32,
+}
+
+/// Pixels scrolled by one step of the mouse wheel.
+const SCROLL_STEP: f32 = 48.;
+
+/// Slider bound to a field of the settings, given with their entity as `(entity, &settings)`.
 macro_rules! slider {
-    ($commands:ident, $settings:ident, $name:ident, $range:expr, float) => {
+    ($commands:ident, $settings:ident, $name:ident, $range:expr, $kind:tt) => {
+        slider!($commands, $settings: Settings, $name, $range, $kind)
+    };
+    ($commands:ident, $settings:ident, $name:ident, $range:expr, $getter:expr) => {
+        slider!($commands, $settings: Settings, $name, $range, $getter)
+    };
+    ($commands:ident, $settings:ident: $type:ident, $name:ident, $range:expr, float) => {
         Slider::spawn(
             $commands,
             SliderSettings {
                 lable: stringify!($name),
-                value: Settings::default().$name as f32,
+                value: $settings.1.$name as f32,
                 range: $range,
                 round_value: false,
                 ..default()
             },
-            ValueBind::<Settings, Slider> {
-                dst: $settings,
+            ValueBind::<$type, Slider> {
+                dst: $settings.0,
                 update: |settings, slider| settings.$name = slider.value as _,
             },
         )
     };
-    ($commands:ident, $settings:ident, $name:ident, $range:expr, int) => {
+    ($commands:ident, $settings:ident: $type:ident, $name:ident, $range:expr, int) => {
         Slider::spawn(
             $commands,
             SliderSettings {
                 lable: stringify!($name),
-                value: Settings::default().$name as f32,
+                value: $settings.1.$name as f32,
                 range: $range,
                 round_value: true,
                 ..default()
             },
-            ValueBind::<Settings, Slider> {
-                dst: $settings,
+            ValueBind::<$type, Slider> {
+                dst: $settings.0,
                 update: |settings, slider| settings.$name = slider.value as _,
             },
         )
     };
-    ($commands:ident, $settings:ident, $name:ident, $range:expr, $getter:expr) => {
+    ($commands:ident, $settings:ident: $type:ident, $name:ident, $range:expr, $getter:expr) => {
         Slider::spawn(
             $commands,
             SliderSettings {
                 lable: stringify!($name),
-                value: 0.,
+                value: slider_value($range, $getter, &$settings.1.$name),
                 range: $range,
                 round_value: true,
                 ..default()
             },
-            ValueBind::<Settings, Slider> {
-                dst: $settings,
+            ValueBind::<$type, Slider> {
+                dst: $settings.0,
                 update: |settings, slider| settings.$name = ($getter)(slider.value),
             },
         )
     };
 }
 
+/// Position of a slider of whole numbers at which `getter` gives `current`.
+fn slider_value<T: PartialEq>(
+    range: RangeInclusive<f32>,
+    getter: impl Fn(f32) -> T,
+    current: &T,
+) -> f32 {
+    let (start, end) = (*range.start() as i32, *range.end() as i32);
+    (start..=end)
+        .map(|value| value as f32)
+        .find(|&value| getter(value) == *current)
+        .unwrap_or(*range.start())
+}
+
 impl SettingsUI {
-    pub fn new(cmd: &mut Commands, settings: Entity) -> Entity {
+    /// Sliders of the settings of `entity`, starting at their current values.
+    pub fn new(
+        cmd: &mut Commands,
+        entity: Entity,
+        settings: &Settings,
+        view_settings: &ViewSettings,
+    ) -> Entity {
         let panel = NodeBundle {
             style: Style {
                 width: Val::Percent(100.),
                 height: Val::Percent(100.),
                 flex_direction: FlexDirection::Column,
                 align_items: AlignItems::Center,
-                justify_content: JustifyContent::Center,
-                row_gap: Val::Px(4.),
+                overflow: Overflow::clip(),
                 ..default()
             },
             background_color: Color::rgb(0., 0., 0.).into(),
             ..default()
         };
+        // Centered when it fits in the panel, starting at the top otherwise
+        let list = NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(4.),
+                margin: UiRect::vertical(Val::Auto),
+                ..default()
+            },
+            ..default()
+        };
 
+        // The sliders write their value on the first frame, so they start at the current one
+        let settings = (entity, settings);
+        let view_settings = (entity, view_settings);
         let rows = &[
             slider!(cmd, settings, diameter, 16.0..=2048.0, int),
             slider!(cmd, settings, darken, 0.0..=254.0, int),
+            slider!(cmd, settings, black_point, 0.0..=254.0, int),
+            slider!(cmd, settings, white_point, 1.0..=255.0, int),
+            slider!(cmd, settings, brightness, -1.0..=1.0, float),
+            slider!(cmd, settings, contrast, 0.0..=3.0, float),
+            slider!(cmd, settings, gamma, 0.2..=5.0, float),
+            slider!(
+                cmd,
+                settings,
+                equalization,
+                0.0..=2.0,
+                |value| match value as u32 {
+                    0 => Equalization::Off,
+                    1 => Equalization::Histogram,
+                    _ => Equalization::Adaptive,
+                }
+            ),
+            slider!(cmd, settings, clahe_clip_limit, 1.0..=8.0, float),
+            slider!(cmd, settings, sharpen_amount, 0.0..=3.0, float),
+            slider!(cmd, settings, sharpen_radius, 0.5..=10.0, float),
+            slider!(cmd, settings, auto_tone, 0.0..=1.0, |value| value != 0.),
             slider!(
                 cmd,
                 settings,
@@ -145,11 +495,152 @@ impl SettingsUI {
                     ColorDistanceFn::ABS
                 }
             ),
+            slider!(cmd, settings, beam_width, 1.0..=16.0, int),
+            slider!(cmd, settings, lookahead, 1.0..=4.0, int),
+            slider!(cmd, settings, refine_iterations, 0.0..=50000.0, int),
+            slider!(cmd, settings, refine_temperature, 0.0..=10000.0, float),
+            slider!(cmd, settings, solver, 0.0..=1.0, |value| if value == 0. {
+                SolverKind::Greedy
+            } else {
+                SolverKind::LeastSquares
+            }),
+            slider!(cmd, settings, lsq_iterations, 10.0..=2000.0, int),
+            slider!(cmd, settings, seed, 0.0..=1000.0, int),
+            slider!(cmd, settings, sample_top_k, 1.0..=16.0, int),
+            slider!(cmd, settings, sample_temperature, 0.0..=1.0, float),
+            slider!(cmd, settings, min_nail_skip, 1.0..=64.0, int),
+            slider!(cmd, settings, max_wraps_per_nail, 0.0..=100.0, int),
+            slider!(cmd, settings, nail_diameter_mm, 0.0..=5.0, float),
+            slider!(cmd, settings, board_diameter_mm, 100.0..=1500.0, int),
+            slider!(cmd, settings, thread_thickness_mm, 0.05..=2.0, float),
+            slider!(
+                cmd,
+                settings,
+                rasterizer,
+                0.0..=2.0,
+                |value| match value as u32 {
+                    0 => Rasterizer::XiaolinWu,
+                    1 => Rasterizer::Gaussian,
+                    _ => Rasterizer::Box,
+                }
+            ),
+            slider!(cmd, settings, supersampling, 1.0..=4.0, int),
+            slider!(cmd, settings, importance_strength, 0.0..=1.0, float),
+            slider!(cmd, settings, keep_out_diameter_mm, 0.0..=300.0, float),
+            slider!(cmd, settings, rings, 1.0..=5.0, int),
+            slider!(cmd, settings, ring_spacing, 0.05..=0.5, float),
+            slider!(
+                cmd,
+                settings,
+                ring_connections,
+                0.0..=2.0,
+                |value| match value as u32 {
+                    0 => RingConnections::All,
+                    1 => RingConnections::ToOuter,
+                    _ => RingConnections::Neighbours,
+                }
+            ),
+            slider!(
+                cmd,
+                settings,
+                wrap_direction,
+                0.0..=2.0,
+                |value| match value as u32 {
+                    0 => WrapDirection::Alternate,
+                    1 => WrapDirection::Clockwise,
+                    _ => WrapDirection::CounterClockwise,
+                }
+            ),
+            slider!(cmd, settings, crop_x, -0.5..=0.5, float),
+            slider!(cmd, settings, crop_y, -0.5..=0.5, float),
+            slider!(cmd, settings, crop_zoom, CROP_ZOOM_RANGE, float),
+            slider!(cmd, settings, crop_rotation, -180.0..=180.0, float),
+            slider!(cmd, settings, board_color, 0.0..=5.0, |value| {
+                BOARD_COLORS[value as usize]
+            }),
+            slider!(cmd, settings, thread_color, 0.0..=5.0, |value| {
+                THREAD_COLORS[value as usize]
+            }),
+            slider!(
+                cmd,
+                view_settings: ViewSettings,
+                show_nail_stats,
+                0.0..=1.0,
+                |value| value != 0.
+            ),
+            slider!(
+                cmd,
+                view_settings: ViewSettings,
+                show_weights,
+                0.0..=1.0,
+                |value| value != 0.
+            ),
+            slider!(
+                cmd,
+                view_settings: ViewSettings,
+                framing,
+                0.0..=1.0,
+                |value| value != 0.
+            ),
+            slider!(
+                cmd,
+                view_settings: ViewSettings,
+                view_mode,
+                0.0..=4.0,
+                |value| match value as u32 {
+                    0 => ViewMode::Result,
+                    1 => ViewMode::Target,
+                    2 => ViewMode::SideBySide,
+                    3 => ViewMode::Blink,
+                    _ => ViewMode::Wipe,
+                }
+            ),
+            slider!(cmd, view_settings: ViewSettings, wipe_position, 0.0..=1.0, float),
+            slider!(
+                cmd,
+                view_settings: ViewSettings,
+                show_error,
+                0.0..=1.0,
+                |value| value != 0.
+            ),
+            slider!(
+                cmd,
+                view_settings: ViewSettings,
+                show_nails,
+                0.0..=1.0,
+                |value| value != 0.
+            ),
+            slider!(cmd, view_settings: ViewSettings, nail_label_interval, 0.0..=50.0, int),
+            slider!(
+                cmd,
+                view_settings: ViewSettings,
+                playback,
+                0.0..=1.0,
+                |value| value != 0.
+            ),
+            slider!(cmd, view_settings: ViewSettings, playback_pace, 0.0..=30.0, float),
+            slider!(
+                cmd,
+                view_settings: ViewSettings,
+                animation_format,
+                0.0..=1.0,
+                |value| match value as u32 {
+                    0 => AnimationFormat::Gif,
+                    _ => AnimationFormat::PngSequence,
+                }
+            ),
+            slider!(cmd, view_settings: ViewSettings, animation_strings_per_frame, 1.0..=500.0, int),
+            slider!(cmd, view_settings: ViewSettings, animation_frame_rate, 1.0..=60.0, int),
+            slider!(cmd, view_settings: ViewSettings, animation_resolution, 64.0..=2048.0, int),
         ];
 
-        let mut menu = cmd.spawn(panel);
-        menu.push_children(rows);
-        menu.id()
+        let list = cmd
+            .spawn((list, SettingsList::default()))
+            .push_children(rows)
+            .id();
+        cmd.spawn((panel, RelativeCursorPosition::default()))
+            .add_child(list)
+            .id()
     }
 }
 
@@ -157,6 +648,106 @@ pub struct SettingsPlugin;
 
 impl Plugin for SettingsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, update_settings::<Settings, Slider>);
+        app.add_systems(
+            Update,
+            (
+                update_settings::<Settings, Slider>,
+                update_settings::<ViewSettings, Slider>,
+                load_nail_clusters,
+                scroll_settings,
+            ),
+        );
+    }
+}
+
+/// The mouse wheel scrolls the sliders while the cursor is over the panel.
+fn scroll_settings(
+    mut list_query: Query<(&mut SettingsList, &mut Style, &Node, &Parent)>,
+    panel_query: Query<(&Node, &RelativeCursorPosition)>,
+    mut wheel: EventReader<MouseWheel>,
+) {
+    let scroll: f32 = wheel.iter().map(|event| event.y.signum()).sum();
+    if scroll == 0. {
+        return;
+    }
+
+    for (mut list, mut style, node, parent) in &mut list_query {
+        let Ok((panel, cursor)) = panel_query.get(parent.get()) else {
+            continue;
+        };
+        if !cursor.mouse_over() {
+            continue;
+        }
+        let overflow = (node.size().y - panel.size().y).max(0.);
+        list.scroll = (list.scroll + scroll * SCROLL_STEP).clamp(-overflow, 0.);
+        style.top = Val::Px(list.scroll);
+    }
+}
+
+/// Path of the nail clusters of a source image: `assets/a.jpg` uses `assets/a.nails.csv`.
+pub fn nail_clusters_path(image_path: &str) -> PathBuf {
+    Path::new(image_path).with_extension("nails.csv")
+}
+
+/// Reads clusters from lines of `x,y,radius,nails`, after a header line.
+fn parse_nail_clusters(csv: &str) -> Option<Vec<NailRing>> {
+    csv.lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let values: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [x, y, radius, nails] = values[..] else {
+                return None;
+            };
+            Some(NailRing {
+                center: (x.parse().ok()?, y.parse().ok()?),
+                radius: radius.parse().ok()?,
+                nails: nails.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Total number of nails of the clusters, `None` when they can not all have a `u16` index.
+fn cluster_nails(clusters: &[NailRing]) -> Option<u16> {
+    clusters
+        .iter()
+        .try_fold(0u16, |count, cluster| count.checked_add(cluster.nails))
+}
+
+/// Loads the nail clusters of the source image when another image is selected.
+fn load_nail_clusters(mut query: Query<&mut Settings>, mut loaded: Local<Option<usize>>) {
+    for mut settings in &mut query {
+        if *loaded == Some(settings.source_image_index) {
+            continue;
+        }
+        *loaded = Some(settings.source_image_index);
+
+        let path = nail_clusters_path(IMAGES_PATHS[settings.source_image_index]);
+        let clusters = match fs::read_to_string(&path) {
+            Ok(csv) => match parse_nail_clusters(&csv) {
+                Some(clusters) if cluster_nails(&clusters).is_none() => {
+                    error!("Too many nails in {}", path.display());
+                    vec![]
+                }
+                Some(clusters) => {
+                    info!(
+                        "Loaded {} nail clusters from {}",
+                        clusters.len(),
+                        path.display()
+                    );
+                    clusters
+                }
+                None => {
+                    error!("Could not parse {}", path.display());
+                    vec![]
+                }
+            },
+            Err(_) => vec![],
+        };
+
+        if settings.clusters != clusters {
+            settings.clusters = clusters;
+        }
     }
 }
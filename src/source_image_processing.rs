@@ -1,5 +1,6 @@
 use crate::*;
-use image::{imageops, DynamicImage, GrayImage};
+use image::{imageops, GrayImage, Luma};
+use std::path::Path;
 
 pub struct SourceImagePlugin;
 
@@ -9,25 +10,142 @@ impl Plugin for SourceImagePlugin {
     }
 }
 
+/// Target of the solvers: the fraction of the board the strings should let through.
 #[derive(Component)]
 pub struct ProcessedImage(pub Option<GrayImage>);
 
-fn process_source_image(mut query: Query<(&mut ProcessedImage, &Settings), Changed<Settings>>) {
-    for (mut processed_image, settings) in &mut query {
+/// Loads an image that goes with the source image, such as a weight map,
+/// which must be framed like the source image to stay aligned with the target.
+pub fn load_source_map(path: &Path) -> Option<GrayImage> {
+    Some(image::open(path).ok()?.into_luma8())
+}
+
+/// Settings that the framing of the source image depends on.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Framing {
+    diameter: u32,
+    x: f32,
+    y: f32,
+    zoom: f32,
+    rotation: f32,
+}
+
+impl Framing {
+    pub fn of(settings: &Settings) -> Self {
+        Self {
+            diameter: settings.diameter,
+            x: settings.crop_x,
+            y: settings.crop_y,
+            zoom: settings.crop_zoom,
+            rotation: settings.crop_rotation,
+        }
+    }
+}
+
+/// Pixels of an image of `size` for each pixel of the frame.
+fn frame_scale((width, height): (u32, u32), settings: &Settings) -> f32 {
+    width.min(height) as f32 / (settings.diameter as f32 * settings.crop_zoom.max(0.1))
+}
+
+/// Position in an image of `size` of a point of the frame.
+pub fn source_position((x, y): (f32, f32), size: (u32, u32), settings: &Settings) -> (f32, f32) {
+    let scale = frame_scale(size, settings);
+    let (sin, cos) = settings.crop_rotation.to_radians().sin_cos();
+    let half = settings.diameter as f32 / 2.;
+
+    let (dx, dy) = ((x - half) * scale, (y - half) * scale);
+    (
+        size.0 as f32 * (0.5 + settings.crop_x) + dx * cos - dy * sin,
+        size.1 as f32 * (0.5 + settings.crop_y) + dx * sin + dy * cos,
+    )
+}
+
+/// Position in the frame of a point of an image of `size`, the inverse of `source_position`.
+pub fn frame_position((x, y): (f32, f32), size: (u32, u32), settings: &Settings) -> (f32, f32) {
+    let scale = frame_scale(size, settings);
+    let (sin, cos) = settings.crop_rotation.to_radians().sin_cos();
+    let half = settings.diameter as f32 / 2.;
+
+    let dx = x - size.0 as f32 * (0.5 + settings.crop_x);
+    let dy = y - size.1 as f32 * (0.5 + settings.crop_y);
+    (
+        (dx * cos + dy * sin) / scale + half,
+        (dy * cos - dx * sin) / scale + half,
+    )
+}
+
+/// Square of `settings.diameter` pixels cut from the image, centered on the image
+/// moved by `crop_x` and `crop_y`, filled by the image at a `crop_zoom` of 1
+/// and rotated by `crop_rotation` degrees. Pixels outside the image are `outside`.
+pub fn frame_image(image: &GrayImage, settings: &Settings, outside: u8) -> GrayImage {
+    let size = settings.diameter;
+    let dimensions = image.dimensions();
+
+    // Shrinking the image first keeps the sampling from aliasing
+    let fill = 1. / frame_scale(dimensions, settings);
+    let (image, fill) = if fill < 1. {
+        let width = (image.width() as f32 * fill).ceil() as u32;
+        let height = (image.height() as f32 * fill).ceil() as u32;
+        let image = imageops::resize(image, width, height, imageops::FilterType::Triangle);
+        (image, fill)
+    } else {
+        (image.clone(), 1.)
+    };
+
+    GrayImage::from_fn(size, size, |x, y| {
+        let point = (x as f32 + 0.5, y as f32 + 0.5);
+        let (source_x, source_y) = source_position(point, dimensions, settings);
+        Luma([sample_bilinear(&image, source_x * fill, source_y * fill).unwrap_or(outside)])
+    })
+}
+
+/// Value of the image at a position, interpolated between the pixel centers,
+/// or `None` outside the image.
+fn sample_bilinear(image: &GrayImage, x: f32, y: f32) -> Option<u8> {
+    let (x, y) = (x - 0.5, y - 0.5);
+    if x < -0.5 || y < -0.5 || x > image.width() as f32 - 0.5 || y > image.height() as f32 - 0.5 {
+        return None;
+    }
+
+    let pixel = |px: f32, py: f32| {
+        let px = px.clamp(0., image.width() as f32 - 1.) as u32;
+        let py = py.clamp(0., image.height() as f32 - 1.) as u32;
+        image.get_pixel(px, py).0[0] as f32
+    };
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+
+    let top = pixel(x0, y0) * (1. - tx) + pixel(x0 + 1., y0) * tx;
+    let bottom = pixel(x0, y0 + 1.) * (1. - tx) + pixel(x0 + 1., y0 + 1.) * tx;
+    Some((top * (1. - ty) + bottom * ty).round() as u8)
+}
+
+fn process_source_image(
+    mut query: Query<(&mut ProcessedImage, &mut WeightMap, &Settings), Changed<Settings>>,
+) {
+    for (mut processed_image, mut weight_map, settings) in &mut query {
         let image_path = IMAGES_PATHS[settings.source_image_index];
 
-        let mut image = image::open(image_path)
-            .unwrap()
-            .grayscale()
-            .resize_to_fill(
-                settings.diameter,
-                settings.diameter,
-                imageops::FilterType::Triangle,
-            )
-            .into_luma8();
+        let source = image::open(image_path).unwrap().into_luma8();
+        // The board around the image is white
+        let mut image = frame_image(&source, settings, 255);
+
+        weight_map.automatic = if settings.importance_strength > 0. {
+            Some(importance_map(&image, settings.importance_strength))
+        } else {
+            None
+        };
 
+        adjust_tones(&mut image, settings);
+
+        // The solvers work with the fraction of the board the strings let through
         for pixel in image.pixels_mut() {
-            pixel.0[0] = u8::saturating_sub(pixel.0[0], settings.darken);
+            let brightness = u8::saturating_sub(pixel.0[0], settings.darken);
+            pixel.0[0] = (settings.target_transmittance(brightness) * 255.).round() as u8;
+        }
+
+        if settings.auto_tone {
+            info!("{}", calibrate_tones(&mut image, settings));
         }
 
         processed_image.0 = Some(image);
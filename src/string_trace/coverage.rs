@@ -0,0 +1,231 @@
+use super::*;
+use image::imageops;
+
+/// Optical density accumulated by the strings on each pixel of the canvas.
+///
+/// Unlike darkening the canvas pixels in place, adding densities can be undone
+/// exactly, which allows removing strings that have already been drawn.
+///
+/// The canvas can be `scale` times larger than the target image, in which case
+/// each target pixel is compared with the average of the canvas pixels it covers.
+pub struct Coverage {
+    width: u32,
+    scale: u32,
+    density: Vec<f32>,
+    /// Sum of the brightness of the canvas pixels covered by each target pixel
+    brightness: Vec<f32>,
+    /// Weight of the error of each target pixel
+    weights: Vec<f32>,
+}
+
+/// Changes of a value of some pixels, such as their density or brightness.
+pub type PixelChanges = Vec<((u32, u32), f32)>;
+
+/// Brightness of a pixel covered by strings with a total optical density.
+fn brightness(density: f32) -> f32 {
+    255. * (-density).exp()
+}
+
+/// Sorts the changes by point and merges the changes of the same point.
+fn merge_changes(mut changes: PixelChanges) -> PixelChanges {
+    changes.sort_unstable_by_key(|(point, _)| (point.1, point.0));
+
+    let mut merged: PixelChanges = Vec::with_capacity(changes.len());
+    for (point, change) in changes {
+        match merged.last_mut() {
+            Some((last, total)) if *last == point => *total += change,
+            _ => merged.push((point, change)),
+        }
+    }
+    merged
+}
+
+impl Coverage {
+    pub fn new(width: u32, height: u32, scale: u32) -> Self {
+        let scale = scale.max(1);
+        Self {
+            width,
+            scale,
+            density: vec![0.; (width * height * scale * scale) as usize],
+            brightness: vec![255. * (scale * scale) as f32; (width * height) as usize],
+            weights: vec![1.; (width * height) as usize],
+        }
+    }
+
+    /// Weighs the error of each target pixel by the brightness of the weight map.
+    /// The weights are normalized to a mean of 1, so the error keeps the same scale.
+    pub fn set_weights(&mut self, weights: &GrayImage) {
+        let height = self.brightness.len() as u32 / self.width;
+        let weights = if weights.dimensions() == (self.width, height) {
+            weights.clone()
+        } else {
+            imageops::resize(weights, self.width, height, imageops::FilterType::Triangle)
+        };
+
+        let mean = weights.pixels().map(|p| p.0[0] as f32).sum::<f32>() / self.weights.len() as f32;
+        if mean > 0. {
+            self.weights = weights.pixels().map(|p| p.0[0] as f32 / mean).collect();
+        }
+    }
+
+    /// Weight of the error of a target pixel.
+    pub fn weight(&self, x: u32, y: u32) -> f32 {
+        self.weights[(x + y * self.width) as usize]
+    }
+
+    /// Width of the target image.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Width of the canvas the strings are drawn on.
+    pub fn canvas_width(&self) -> u32 {
+        self.width * self.scale
+    }
+
+    pub fn densities(&self) -> &[f32] {
+        &self.density
+    }
+
+    /// Gives direct access to the densities of the canvas pixels.
+    /// [`Coverage::update_brightness`] must be called after modifying them.
+    pub fn densities_mut(&mut self) -> &mut [f32] {
+        &mut self.density
+    }
+
+    /// Recomputes the brightness of the target pixels from the densities of the canvas.
+    pub fn update_brightness(&mut self) {
+        self.brightness.fill(0.);
+        let canvas_width = self.canvas_width();
+        for (index, density) in self.density.iter().enumerate() {
+            let x = index as u32 % canvas_width / self.scale;
+            let y = index as u32 / canvas_width / self.scale;
+            self.brightness[(x + y * self.width) as usize] += brightness(*density);
+        }
+    }
+
+    /// Target pixel covered by a canvas pixel.
+    fn target_point(&self, (x, y): (u32, u32)) -> (u32, u32) {
+        (x / self.scale, y / self.scale)
+    }
+
+    /// Density of a pixel of the canvas.
+    pub fn density(&self, x: u32, y: u32) -> f32 {
+        self.density[(x + y * self.canvas_width()) as usize]
+    }
+
+    /// Sum of the brightness of the canvas pixels covered by a target pixel.
+    pub fn brightness_sum(&self, x: u32, y: u32) -> f32 {
+        self.brightness[(x + y * self.width) as usize]
+    }
+
+    /// Color of a target pixel, with a given sum of the brightness of its canvas pixels.
+    fn color(&self, brightness_sum: f32) -> u8 {
+        (brightness_sum / (self.scale * self.scale) as f32)
+            .round()
+            .clamp(0., 255.) as u8
+    }
+
+    /// Color of a pixel of the target image.
+    pub fn pixel(&self, x: u32, y: u32) -> u8 {
+        self.color(self.brightness_sum(x, y))
+    }
+
+    /// Adds (or removes, with a negative value) density to a pixel of the canvas.
+    pub fn add(&mut self, x: u32, y: u32, density: f32) {
+        let index = (x + y * self.canvas_width()) as usize;
+        let old_brightness = brightness(self.density[index]);
+        self.density[index] = (self.density[index] + density).max(0.);
+
+        let (tx, ty) = self.target_point((x, y));
+        self.brightness[(tx + ty * self.width) as usize] +=
+            brightness(self.density[index]) - old_brightness;
+    }
+
+    /// Writes the color of every target pixel into the image, with the thread
+    /// and board colors of the settings.
+    pub fn render(&self, canvas: &mut MutImage, settings: &Settings) {
+        let pixels = canvas.data.chunks_exact_mut(4);
+        for (pixel, brightness) in pixels.zip(&self.brightness) {
+            let transmittance = self.color(*brightness) as f32 / 255.;
+            pixel.copy_from_slice(&settings.composite(transmittance));
+        }
+    }
+
+    /// Total weighted error between the canvas and the target image.
+    pub fn error(&self, image: &GrayImage, settings: &Settings) -> i64 {
+        let error: f64 = image
+            .enumerate_pixels()
+            .map(|(x, y, target)| {
+                let error = settings.color_dist(*target, Luma([self.pixel(x, y)]));
+                (error as f32 * self.weight(x, y)) as f64
+            })
+            .sum();
+        error.round() as i64
+    }
+
+    /// Change of the brightness of the target pixels if the canvas pixels changed
+    /// their densities, starting from the densities given by `density_at`.
+    pub fn brightness_changes(
+        &self,
+        density_changes: PixelChanges,
+        density_at: impl Fn(u32, u32) -> f32,
+    ) -> PixelChanges {
+        let changes = merge_changes(density_changes)
+            .into_iter()
+            .map(|(point, change)| {
+                let density = density_at(point.0, point.1);
+                let change = brightness((density + change).max(0.)) - brightness(density);
+                (self.target_point(point), change)
+            })
+            .collect();
+        merge_changes(changes)
+    }
+
+    /// Change of the weighted error of the target pixels for merged `brightness_changes`,
+    /// starting from the brightness sums given by `brightness_at`.
+    pub fn error_change(
+        &self,
+        brightness_changes: &[((u32, u32), f32)],
+        brightness_at: impl Fn(u32, u32) -> f32,
+        image: &GrayImage,
+        settings: &Settings,
+    ) -> i64 {
+        let delta: f64 = brightness_changes
+            .iter()
+            .map(|&((x, y), change)| {
+                let target = *image.get_pixel(x, y);
+                let brightness_sum = brightness_at(x, y);
+                let pixel = self.color(brightness_sum);
+                let new_pixel = self.color(brightness_sum + change);
+
+                let delta = settings.color_dist(target, Luma([new_pixel]))
+                    - settings.color_dist(target, Luma([pixel]));
+                (delta as f32 * self.weight(x, y)) as f64
+            })
+            .sum();
+        delta.round() as i64
+    }
+
+    /// Change of the total error if the `removed` strings were erased and
+    /// the `added` strings were drawn. Strings are given as `(node_a, node_b, step)`.
+    pub fn error_delta(
+        &self,
+        removed: &[(u16, u16, usize)],
+        added: &[(u16, u16, usize)],
+        image: &GrayImage,
+        settings: &Settings,
+    ) -> i64 {
+        let mut changes = vec![];
+        for (strings, sign) in [(removed, -1.), (added, 1.)] {
+            for &(a, b, step) in strings {
+                settings.trace_string(a, b, step, |point, alpha| {
+                    changes.push((point, sign * string_density(alpha, settings)));
+                });
+            }
+        }
+
+        let changes = self.brightness_changes(changes, |x, y| self.density(x, y));
+        self.error_change(&changes, |x, y| self.brightness_sum(x, y), image, settings)
+    }
+}
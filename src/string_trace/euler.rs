@@ -0,0 +1,266 @@
+use std::collections::{HashSet, VecDeque};
+
+/// Adds the strings needed to join all the `strings` into a single continuous path.
+///
+/// Disconnected groups of strings are linked together first, and then the nodes with an
+/// odd number of strings are paired up, except for the two that will be the ends of the path.
+/// Nodes that `can_connect` rejects are joined through the fewest intermediate nodes that
+/// `can_wrap` accepts, without repeating strings unless `repeat_lines` is set.
+///
+/// Groups that cannot be linked are removed. Returns the number of strings removed.
+pub fn add_connectors(
+    nodes: u16,
+    strings: &mut Vec<(u16, u16)>,
+    repeat_lines: bool,
+    can_connect: impl Fn(u16, u16) -> bool,
+    can_wrap: impl Fn(u32) -> bool,
+) -> usize {
+    let nodes = nodes as usize;
+    let mut group: Vec<usize> = (0..nodes).collect();
+    let mut degree = vec![0u32; nodes];
+
+    fn root(group: &mut [usize], mut node: usize) -> usize {
+        while group[node] != node {
+            group[node] = group[group[node]];
+            node = group[node];
+        }
+        node
+    }
+
+    for &(a, b) in strings.iter() {
+        let (a, b) = (a as usize, b as usize);
+        degree[a] += 1;
+        degree[b] += 1;
+        let (root_a, root_b) = (root(&mut group, a), root(&mut group, b));
+        group[root_a] = root_b;
+    }
+
+    // One node for each group of strings, preferably with an odd degree
+    let mut representatives: Vec<Option<usize>> = vec![None; nodes];
+    for node in (0..nodes).filter(|&node| degree[node] > 0) {
+        let representative = &mut representatives[root(&mut group, node)];
+        match representative {
+            Some(current)
+                if !degree[*current].is_multiple_of(2) || degree[node].is_multiple_of(2) => {}
+            _ => *representative = Some(node),
+        }
+    }
+
+    let connect = |strings: &mut Vec<(u16, u16)>, degree: &mut [u32], a: usize, b: usize| {
+        let Some(connector) = find_connector(
+            a as u16,
+            b as u16,
+            strings,
+            degree,
+            repeat_lines,
+            &can_connect,
+            &can_wrap,
+        ) else {
+            return false;
+        };
+
+        let mut previous = a as u16;
+        for node in connector {
+            degree[previous as usize] += 1;
+            degree[node as usize] += 1;
+            strings.push((previous, node));
+            previous = node;
+        }
+        true
+    };
+
+    // Groups are linked to the first one, and the ones that cannot be are left out
+    let count_before = strings.len();
+    let representatives: Vec<usize> = representatives.into_iter().flatten().collect();
+    let mut left_out = HashSet::new();
+    if let Some((&first, others)) = representatives.split_first() {
+        let mut last = first;
+        for &representative in others {
+            if connect(strings, &mut degree, last, representative) {
+                last = representative;
+            } else {
+                left_out.insert(root(&mut group, representative));
+            }
+        }
+    }
+
+    // Only the strings of the groups are removed, the connectors may go through them
+    let count = strings.len();
+    let mut index = 0;
+    strings.retain(|&(a, b)| {
+        let (a, b) = (a as usize, b as usize);
+        let original = index < count_before;
+        index += 1;
+        let removed = original && left_out.contains(&root(&mut group, a));
+        if removed {
+            degree[a] -= 1;
+            degree[b] -= 1;
+        }
+        !removed
+    });
+    let removed = count - strings.len();
+
+    // Neighbouring odd nodes are paired to keep the connectors short
+    let odd: Vec<usize> = (0..nodes).filter(|&node| degree[node] % 2 == 1).collect();
+    if odd.len() > 2 {
+        for pair in odd[1..odd.len() - 1].chunks(2) {
+            connect(strings, &mut degree, pair[0], pair[1]);
+        }
+    }
+    removed
+}
+
+/// Shortest chain of strings from `a` to `b`, found with a breadth-first search.
+/// Returns the nodes of the chain after `a`.
+fn find_connector(
+    a: u16,
+    b: u16,
+    strings: &[(u16, u16)],
+    degree: &[u32],
+    repeat_lines: bool,
+    can_connect: impl Fn(u16, u16) -> bool,
+    can_wrap: impl Fn(u32) -> bool,
+) -> Option<Vec<u16>> {
+    if !can_wrap(degree[a as usize] / 2) || !can_wrap(degree[b as usize] / 2) {
+        return None;
+    }
+
+    let key = |a: u16, b: u16| (a.min(b), a.max(b));
+    let used: HashSet<(u16, u16)> = if repeat_lines {
+        HashSet::new()
+    } else {
+        strings.iter().map(|&(a, b)| key(a, b)).collect()
+    };
+    let can_add = |from: u16, to: u16| can_connect(from, to) && !used.contains(&key(from, to));
+
+    let mut previous: Vec<Option<u16>> = vec![None; degree.len()];
+    let mut queue = VecDeque::from([a]);
+    while let Some(node) = queue.pop_front() {
+        if can_add(node, b) {
+            let mut connector = vec![b];
+            let mut current = node;
+            while current != a {
+                connector.push(current);
+                current = previous[current as usize].unwrap();
+            }
+            connector.reverse();
+            return Some(connector);
+        }
+
+        for next in 0..degree.len() as u16 {
+            if next == a
+                || previous[next as usize].is_some()
+                || !can_wrap(degree[next as usize] / 2)
+                || !can_add(node, next)
+            {
+                continue;
+            }
+            previous[next as usize] = Some(node);
+            queue.push_back(next);
+        }
+    }
+    None
+}
+
+/// Removes the detours where the path goes to a node and straight back,
+/// which a path built from repeated strings may contain.
+pub fn remove_backtracks(path: &mut Vec<u16>) {
+    let mut result: Vec<u16> = Vec::with_capacity(path.len());
+    for &node in path.iter() {
+        result.push(node);
+        while result.len() >= 3 && result[result.len() - 1] == result[result.len() - 3] {
+            result.truncate(result.len() - 2);
+        }
+    }
+    *path = result;
+}
+
+/// Hierholzer's algorithm. Returns the nodes of a path that goes through every string once.
+///
+/// The strings must be connected and have at most two nodes with an odd number of strings.
+/// Otherwise, the walk breaks into several trails and only the longest one is returned.
+pub fn eulerian_path(nodes: u16, strings: &[(u16, u16)]) -> Vec<u16> {
+    let Some(&(first, _)) = strings.first() else {
+        return vec![0];
+    };
+
+    let mut adjacency = vec![vec![]; nodes as usize];
+    for (string, &(a, b)) in strings.iter().enumerate() {
+        adjacency[a as usize].push((b, string));
+        adjacency[b as usize].push((a, string));
+    }
+
+    let start = (0..nodes)
+        .find(|&node| adjacency[node as usize].len() % 2 == 1)
+        .unwrap_or(first);
+
+    let mut used = vec![false; strings.len()];
+    // Nodes of the walk, with the node each one was reached from
+    let mut stack: Vec<(u16, Option<u16>)> = vec![(start, None)];
+    let mut trails: Vec<Vec<u16>> = vec![vec![]];
+    let mut expected = None;
+
+    while let Some(&(node, from)) = stack.last() {
+        let edges = &mut adjacency[node as usize];
+        while edges.last().is_some_and(|&(_, string)| used[string]) {
+            edges.pop();
+        }
+
+        match edges.pop() {
+            Some((next, string)) => {
+                used[string] = true;
+                stack.push((next, Some(node)));
+            }
+            None => {
+                // The previous node of the path must be joined to this one by a string
+                let trail = trails.last_mut().unwrap();
+                if !trail.is_empty() && expected != Some(node) {
+                    trails.push(vec![]);
+                }
+                trails.last_mut().unwrap().push(node);
+                expected = from;
+                stack.pop();
+            }
+        }
+    }
+
+    let mut path = trails.into_iter().max_by_key(|trail| trail.len()).unwrap();
+    path.reverse();
+    path
+}
+
+#[test]
+fn tests() {
+    let mut strings = vec![(0, 3), (3, 5), (5, 0), (5, 8), (1, 4), (4, 1)];
+    add_connectors(10, &mut strings, true, |a, b| a.abs_diff(b) > 1, |_| true);
+    assert!(strings.iter().all(|&(a, b)| a.abs_diff(b) > 1));
+
+    let path = eulerian_path(10, &strings);
+    assert_eq!(path.len(), strings.len() + 1);
+
+    let mut path_strings: Vec<_> = path
+        .windows(2)
+        .map(|pair| (pair[0].min(pair[1]), pair[0].max(pair[1])))
+        .collect();
+    let mut strings: Vec<_> = strings.iter().map(|&(a, b)| (a.min(b), a.max(b))).collect();
+    path_strings.sort();
+    strings.sort();
+    assert_eq!(path_strings, strings);
+
+    // Joined through several intermediate nodes, without repeating strings
+    let mut strings = vec![(0, 2), (7, 9)];
+    let can_connect = |a: u16, b: u16| (2..=3).contains(&a.abs_diff(b));
+    assert_eq!(
+        add_connectors(10, &mut strings, false, can_connect, |_| true),
+        0
+    );
+    assert!(strings.len() > 3);
+    assert!(strings.iter().all(|&(a, b)| can_connect(a, b)));
+    assert_eq!(eulerian_path(10, &strings).len(), strings.len() + 1);
+
+    // Groups that cannot be joined are left out
+    let mut strings = vec![(0, 2), (2, 4), (6, 8)];
+    let removed = add_connectors(10, &mut strings, false, |a, b| a / 5 == b / 5, |_| true);
+    assert_eq!(removed, 1);
+    assert_eq!(strings, vec![(0, 2), (2, 4)]);
+}
@@ -0,0 +1,158 @@
+use super::*;
+
+/// Draws, one at a time, the string from the last node that improves the canvas the most.
+pub struct GreedySolver {
+    pub traced_nodes: Vec<u16>,
+    pub coverage: Coverage,
+    pub line_set: LineSet,
+    /// Times the path goes through each node
+    pub wraps: Vec<u32>,
+    pub refinement: Option<Refinement>,
+    pub keep_out: KeepOut,
+    pub done: bool,
+    rng: Rng,
+}
+
+impl GreedySolver {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            traced_nodes: vec![0],
+            coverage: Coverage::new(settings.diameter, settings.diameter, settings.supersampling),
+            line_set: LineSet::new(settings.node_count()),
+            wraps: (0..settings.node_count())
+                .map(|node| (node == 0) as u32)
+                .collect(),
+            refinement: None,
+            keep_out: KeepOut::default(),
+            done: false,
+            rng: Rng::new(settings.seed),
+        }
+    }
+
+    fn trace_best(&mut self, target_image: &GrayImage, settings: &Settings) {
+        let Some(next_node_index) = self.best_next_string(target_image, settings) else {
+            self.finish_tracing(target_image, settings);
+            return;
+        };
+
+        let node_index = *self.traced_nodes.last().unwrap();
+        let step = self.traced_nodes.len() - 1;
+        self.draw_string(node_index, next_node_index, step, 1., settings);
+
+        if !settings.repeat_lines {
+            self.line_set.add(node_index, next_node_index);
+        }
+
+        self.traced_nodes.push(next_node_index);
+        self.wraps[next_node_index as usize] += 1;
+
+        if self.traced_nodes.len() > settings.max_lines {
+            self.finish_tracing(target_image, settings);
+        }
+    }
+
+    /// Starts the refinement of the traced strings, if enabled.
+    fn finish_tracing(&mut self, target_image: &GrayImage, settings: &Settings) {
+        if settings.refine_iterations > 0 && self.traced_nodes.len() > 1 {
+            let error = self.coverage.error(target_image, settings);
+            self.refinement = Some(Refinement::new(error, self.rng.next_u64()));
+        } else {
+            self.done = true;
+        }
+    }
+
+    /// Draws a string on the canvas, or erases it if `sign` is negative.
+    pub(super) fn draw_string(
+        &mut self,
+        node_a: u16,
+        node_b: u16,
+        step: usize,
+        sign: f32,
+        settings: &Settings,
+    ) {
+        let coverage = &mut self.coverage;
+        settings.trace_string(node_a, node_b, step, |point, alpha| {
+            coverage.add(point.0, point.1, sign * string_density(alpha, settings));
+        });
+    }
+
+    /// Picks the next node among the best `settings.sample_top_k` candidates, with a
+    /// probability that decreases with the performance relative to the best one.
+    /// Only the candidates whose sequence improves the canvas are kept, so tracing stops
+    /// when no sequence of `settings.lookahead` strings improves it.
+    fn best_next_string(&mut self, image: &GrayImage, settings: &Settings) -> Option<u16> {
+        let mut candidates = self.beam_search(image, settings);
+        candidates.retain(|&(performance, _)| performance > 0);
+        let &(best_performance, best_node) = candidates.first()?;
+
+        if settings.sample_top_k <= 1 || settings.sample_temperature <= 0. {
+            return Some(best_node);
+        }
+
+        let weights: Vec<f32> = candidates
+            .iter()
+            .take(settings.sample_top_k)
+            .map(|&(performance, _)| {
+                let relative = performance as f32 / best_performance as f32 - 1.;
+                f32::exp(relative / settings.sample_temperature)
+            })
+            .collect();
+
+        let mut choice = self.rng.next_f32() * weights.iter().sum::<f32>();
+        for (weight, &(_, node)) in weights.iter().zip(&candidates) {
+            if choice < *weight {
+                return Some(node);
+            }
+            choice -= weight;
+        }
+        Some(best_node)
+    }
+}
+
+impl Solver for GreedySolver {
+    fn name(&self) -> &'static str {
+        "greedy"
+    }
+
+    fn init(
+        &mut self,
+        _image: &GrayImage,
+        weights: Option<&GrayImage>,
+        keep_out: KeepOut,
+        settings: &Settings,
+    ) {
+        *self = Self::new(settings);
+        if let Some(weights) = weights {
+            self.coverage.set_weights(weights);
+        }
+        self.keep_out = keep_out;
+    }
+
+    fn step(&mut self, image: &GrayImage, settings: &Settings) {
+        if self.done {
+            return;
+        }
+
+        if self.refinement.is_some() {
+            self.refine(image, settings);
+        } else {
+            self.trace_best(image, settings);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn sequence(&self) -> &[u16] {
+        &self.traced_nodes
+    }
+
+    fn coverage(&self) -> &Coverage {
+        &self.coverage
+    }
+
+    fn steps_per_frame(&self, settings: &Settings) -> usize {
+        (128 / (settings.beam_width * settings.lookahead).max(1)).max(1)
+    }
+}
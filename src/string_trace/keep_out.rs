@@ -0,0 +1,76 @@
+use super::*;
+use image::imageops;
+
+/// Areas of the board that strings may not cross: a hole in the center of the board
+/// and the white pixels of a mask image.
+#[derive(Default)]
+pub struct KeepOut {
+    width: u32,
+    pixels: Vec<bool>,
+}
+
+impl KeepOut {
+    pub fn new(mask: Option<&GrayImage>, settings: &Settings) -> Self {
+        let size = settings.diameter;
+        let mask =
+            mask.map(|mask| imageops::resize(mask, size, size, imageops::FilterType::Nearest));
+
+        let center = (size - 2) as f32 / 2.;
+        let radius = settings.keep_out_diameter_mm * settings.pixels_per_mm() / 2.;
+
+        let pixels: Vec<bool> = (0..size * size)
+            .map(|index| {
+                let (x, y) = (index % size, index / size);
+                let in_hole = f32::hypot(x as f32 - center, y as f32 - center) < radius;
+                let in_mask = mask
+                    .as_ref()
+                    .is_some_and(|mask| mask.get_pixel(x, y).0[0] >= 128);
+                in_hole || in_mask
+            })
+            .collect();
+
+        if pixels.iter().any(|&pixel| pixel) {
+            Self {
+                width: size,
+                pixels,
+            }
+        } else {
+            Self::default()
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.width && self.pixels[(x + y * self.width) as usize]
+    }
+
+    /// Whether a straight thread between two points of the target image crosses the area.
+    pub fn crosses(&self, a: (f32, f32), b: (f32, f32)) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        let samples = (f32::hypot(b.0 - a.0, b.1 - a.1) * 2.).ceil().max(1.) as u32;
+        (0..=samples).any(|i| {
+            let t = i as f32 / samples as f32;
+            let (x, y) = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+            x >= 0. && y >= 0. && self.contains(x.round() as u32, y.round() as u32)
+        })
+    }
+
+    /// Whether a string of the path crosses the area. `step` is the position of `node_a` in the path.
+    pub fn blocks(&self, node_a: u16, node_b: u16, step: usize, settings: &Settings) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let (a, b) = settings.string_ends(node_a, node_b, step);
+        self.crosses(a, b)
+    }
+}
@@ -0,0 +1,315 @@
+use super::*;
+use rayon::prelude::*;
+
+const POWER_ITERATIONS: usize = 16;
+
+enum Phase {
+    Optimizing,
+    Drawing,
+    Done,
+}
+
+/// Finds how many times each string should be drawn by solving a non-negative
+/// least squares problem between the densities of all the possible strings and
+/// the density of the target image, using projected gradient descent.
+/// The resulting strings are then joined into a single path.
+pub struct LeastSquaresSolver {
+    strings: Vec<(u16, u16)>,
+    /// Times each string is used, relaxed to real numbers
+    uses: Vec<f32>,
+    target: Vec<f32>,
+    /// Weight of the error of each canvas pixel
+    weights: Vec<f32>,
+    step_size: f32,
+    iteration: usize,
+    path: Vec<u16>,
+    drawn: usize,
+    coverage: Coverage,
+    keep_out: KeepOut,
+    phase: Phase,
+}
+
+impl LeastSquaresSolver {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            strings: vec![],
+            uses: vec![],
+            target: vec![],
+            weights: vec![],
+            step_size: 0.,
+            iteration: 0,
+            path: vec![0],
+            drawn: 0,
+            coverage: Coverage::new(settings.diameter, settings.diameter, settings.supersampling),
+            keep_out: KeepOut::default(),
+            phase: Phase::Optimizing,
+        }
+    }
+
+    /// Whether a string may join two nodes, before the path and the wrap sides are known.
+    fn can_draw(&self, node_a: u16, node_b: u16, settings: &Settings) -> bool {
+        settings.can_connect(node_a, node_b)
+            && !self
+                .keep_out
+                .crosses(settings.node_pos(node_a), settings.node_pos(node_b))
+    }
+
+    /// Gradient of the squared error with respect to the uses of each string.
+    fn gradient(&self, residual: &[f32], settings: &Settings) -> Vec<f32> {
+        let width = self.coverage.canvas_width();
+        self.strings
+            .par_iter()
+            .map(|&string| {
+                let mut gradient = 0.;
+                string_pixels(string, width, settings, |pixel, d| {
+                    gradient += d * residual[pixel]
+                });
+                gradient
+            })
+            .collect()
+    }
+
+    /// Estimates the largest eigenvalue of AᵀA with the power method,
+    /// which bounds the step size that keeps the gradient descent stable.
+    fn lipschitz_constant(&self, settings: &Settings) -> f32 {
+        let mut vector = vec![1. / (self.strings.len() as f32).sqrt(); self.strings.len()];
+        let mut density = vec![0.; self.target.len()];
+        let mut eigenvalue = 0.;
+
+        for _ in 0..POWER_ITERATIONS {
+            let width = self.coverage.canvas_width();
+            forward(&self.strings, &vector, width, settings, &mut density);
+            for (density, weight) in density.iter_mut().zip(&self.weights) {
+                *density *= weight;
+            }
+            let next = self.gradient(&density, settings);
+            eigenvalue = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+            if eigenvalue == 0. {
+                break;
+            }
+            vector = next.into_iter().map(|v| v / eigenvalue).collect();
+        }
+        eigenvalue
+    }
+
+    fn optimize(&mut self, settings: &Settings) {
+        let residual: Vec<f32> = self
+            .coverage
+            .densities()
+            .iter()
+            .zip(&self.target)
+            .zip(&self.weights)
+            .map(|((density, target), weight)| (density - target) * weight)
+            .collect();
+        let gradient = self.gradient(&residual, settings);
+
+        let max_uses = if settings.repeat_lines { f32::MAX } else { 1. };
+        for (uses, gradient) in self.uses.iter_mut().zip(gradient) {
+            *uses = (*uses - self.step_size * gradient).clamp(0., max_uses);
+        }
+
+        let width = self.coverage.canvas_width();
+        forward(
+            &self.strings,
+            &self.uses,
+            width,
+            settings,
+            self.coverage.densities_mut(),
+        );
+        self.coverage.update_brightness();
+
+        self.iteration += 1;
+        if self.iteration >= settings.lsq_iterations {
+            self.build_path(settings);
+        }
+    }
+
+    /// Rounds the uses of each string and joins them into a single path.
+    fn build_path(&mut self, settings: &Settings) {
+        let nodes = settings.node_count();
+
+        // The connectors count towards the strings, which are reduced until both fit
+        let mut budget = settings.max_lines;
+        let (strings, removed) = loop {
+            let mut strings = self.round_strings(budget, settings);
+            let removed = add_connectors(
+                nodes,
+                &mut strings,
+                settings.repeat_lines,
+                |a, b| self.can_draw(a, b, settings),
+                |wraps| settings.can_wrap(wraps),
+            );
+            let excess = strings.len().saturating_sub(settings.max_lines);
+            if excess == 0 || budget == 0 {
+                break (strings, removed);
+            }
+            budget -= excess.min(budget);
+        };
+        if removed > 0 {
+            warn!("{} strings could not be joined to the path", removed);
+        }
+
+        self.path = eulerian_path(nodes, &strings);
+        let left_out = (strings.len() + 1).saturating_sub(self.path.len());
+        if left_out > 0 {
+            warn!("{} strings are not on the path", left_out);
+        }
+        remove_backtracks(&mut self.path);
+
+        self.coverage.densities_mut().fill(0.);
+        self.coverage.update_brightness();
+        self.phase = Phase::Drawing;
+    }
+
+    /// Strings drawn as many times as their rounded uses, the most used first,
+    /// up to `budget` strings and within the wrap limit of the nails.
+    fn round_strings(&self, budget: usize, settings: &Settings) -> Vec<(u16, u16)> {
+        let mut order: Vec<usize> = (0..self.strings.len()).collect();
+        order.sort_by(|a, b| self.uses[*b].total_cmp(&self.uses[*a]));
+
+        // The path wraps around a node once for every two strings that reach it
+        let mut degree = vec![0; settings.node_count() as usize];
+
+        let mut strings = vec![];
+        'strings: for string in order {
+            let (a, b) = self.strings[string];
+            for _ in 0..self.uses[string].round() as usize {
+                if strings.len() >= budget {
+                    break 'strings;
+                }
+                if !settings.can_wrap(degree[a as usize] / 2)
+                    || !settings.can_wrap(degree[b as usize] / 2)
+                {
+                    break;
+                }
+                degree[a as usize] += 1;
+                degree[b as usize] += 1;
+                strings.push((a, b));
+            }
+        }
+        strings
+    }
+
+    fn draw_next(&mut self, settings: &Settings) {
+        let Some(&[a, b]) = self.path.get(self.drawn..self.drawn + 2) else {
+            self.phase = Phase::Done;
+            return;
+        };
+
+        let coverage = &mut self.coverage;
+        settings.trace_string(a, b, self.drawn, |point, alpha| {
+            coverage.add(point.0, point.1, string_density(alpha, settings));
+        });
+        self.drawn += 1;
+    }
+}
+
+/// Pixels of a string between the centers of two nails.
+/// The side of the nails depends on the path, which is not known while optimizing.
+fn string_pixels(
+    (a, b): (u16, u16),
+    width: u32,
+    settings: &Settings,
+    mut f: impl FnMut(usize, f32),
+) {
+    settings.rasterize(
+        settings.node_pos(a),
+        settings.node_pos(b),
+        |point, alpha| {
+            f(
+                (point.0 + point.1 * width) as usize,
+                string_density(alpha, settings),
+            );
+        },
+    );
+}
+
+/// Density of each pixel when every string is drawn as many times as its value.
+fn forward(
+    strings: &[(u16, u16)],
+    values: &[f32],
+    width: u32,
+    settings: &Settings,
+    density: &mut [f32],
+) {
+    density.fill(0.);
+    for (&string, &value) in strings.iter().zip(values) {
+        if value != 0. {
+            string_pixels(string, width, settings, |pixel, d| {
+                density[pixel] += value * d
+            });
+        }
+    }
+}
+
+impl Solver for LeastSquaresSolver {
+    fn name(&self) -> &'static str {
+        "least squares"
+    }
+
+    fn init(
+        &mut self,
+        image: &GrayImage,
+        weights: Option<&GrayImage>,
+        keep_out: KeepOut,
+        settings: &Settings,
+    ) {
+        *self = Self::new(settings);
+        if let Some(weights) = weights {
+            self.coverage.set_weights(weights);
+        }
+        self.keep_out = keep_out;
+
+        let nodes = settings.node_count();
+        self.strings = (0..nodes)
+            .flat_map(|a| (a + 1..nodes).map(move |b| (a, b)))
+            .filter(|&(a, b)| self.can_draw(a, b, settings))
+            .collect();
+        self.uses = vec![0.; self.strings.len()];
+        // Every canvas pixel aims for the density of the target pixel that covers it
+        let scale = settings.supersampling.max(1);
+        let canvas_size = settings.canvas_size();
+        let target_points = (0..canvas_size * canvas_size)
+            .map(|index| (index % canvas_size / scale, index / canvas_size / scale));
+        self.target = target_points
+            .clone()
+            .map(|(x, y)| -f32::ln(image.get_pixel(x, y).0[0].max(1) as f32 / 255.))
+            .collect();
+        self.weights = target_points
+            .map(|(x, y)| self.coverage.weight(x, y))
+            .collect();
+
+        let lipschitz = self.lipschitz_constant(settings);
+        self.step_size = if lipschitz > 0. { 1. / lipschitz } else { 0. };
+    }
+
+    fn step(&mut self, _image: &GrayImage, settings: &Settings) {
+        match self.phase {
+            Phase::Optimizing => self.optimize(settings),
+            Phase::Drawing => self.draw_next(settings),
+            Phase::Done => {}
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        matches!(self.phase, Phase::Done)
+    }
+
+    fn sequence(&self) -> &[u16] {
+        match self.phase {
+            Phase::Optimizing => &self.path[..1],
+            Phase::Drawing | Phase::Done => &self.path[..=self.drawn],
+        }
+    }
+
+    fn coverage(&self) -> &Coverage {
+        &self.coverage
+    }
+
+    fn steps_per_frame(&self, _settings: &Settings) -> usize {
+        match self.phase {
+            Phase::Optimizing => 1,
+            Phase::Drawing | Phase::Done => 128,
+        }
+    }
+}
@@ -1,35 +1,45 @@
 use super::*;
 
-pub struct LineSet([u8; 256 * 256 / 8]);
+/// Set of the strings drawn between pairs of nodes, in either direction.
+pub struct LineSet {
+    nodes: usize,
+    bits: Vec<u64>,
+}
 
 impl LineSet {
-    pub fn new() -> Self {
-        Self([0; 256 * 256 / 8])
+    pub fn new(nodes: u16) -> Self {
+        let nodes = nodes as usize;
+        Self {
+            nodes,
+            bits: vec![0; (nodes * nodes).div_ceil(64)],
+        }
+    }
+
+    fn bit(&self, a: u16, b: u16) -> (usize, u64) {
+        let hash = a.max(b) as usize * self.nodes + a.min(b) as usize;
+        (hash / 64, 1 << (hash % 64))
     }
+
     pub fn add(&mut self, a: u16, b: u16) {
-        if b > a {
-            self.add(b, a);
-        } else {
-            let hash = (a << 8) | b;
-            let index = (hash >> 3) as usize;
-            self.0[index] = self.0[index] | (1 << (hash & 7));
-        }
+        let (index, mask) = self.bit(a, b);
+        self.bits[index] |= mask;
+    }
+
+    pub fn remove(&mut self, a: u16, b: u16) {
+        let (index, mask) = self.bit(a, b);
+        self.bits[index] &= !mask;
     }
 
     pub fn has(&self, a: u16, b: u16) -> bool {
-        if b > a {
-            self.has(b, a)
-        } else {
-            let hash = (a << 8) | b;
-            let index = (hash >> 3) as usize;
-            self.0[index] & (1 << (hash & 7)) != 0
-        }
+        let (index, mask) = self.bit(a, b);
+        self.bits[index] & mask != 0
     }
 }
 
-pub fn overlay_string(pixel: u8, string_alpha: f32, settings: &Settings) -> u8 {
-    let c = 1. - string_alpha * settings.string_alpha;
-    (pixel as f32 * c) as u8
+/// Optical density added to a pixel covered by `string_alpha` of a string.
+pub fn string_density(string_alpha: f32, settings: &Settings) -> f32 {
+    let transmittance = 1. - (string_alpha * settings.string_alpha).min(0.99);
+    -transmittance.ln()
 }
 
 /// Xiaolin Wu’s line algorithm.
@@ -76,6 +86,77 @@ pub fn trace_line<F: FnMut((u32, u32), f32)>(
     }
 }
 
+/// Cross-section of a thread `width` pixels wide, box filtered over each pixel.
+/// Returns the fraction of a pixel covered at a distance from the thread center,
+/// and the distance beyond which nothing is covered.
+pub fn box_profile(width: f32) -> (impl Fn(f32) -> f32, f32) {
+    let half = width / 2.;
+    let profile = move |d: f32| (f32::min(d + 0.5, half) - f32::max(d - 0.5, -half)).max(0.);
+    (profile, half + 0.5)
+}
+
+/// Gaussian cross-section with a full width at half maximum of `width` pixels.
+/// Like the box profile, it integrates to `width` across the thread.
+pub fn gaussian_profile(width: f32) -> (impl Fn(f32) -> f32, f32) {
+    let sigma = (width / 2.3548).max(0.5);
+    let peak = width / (sigma * (2. * PI).sqrt());
+    let profile = move |d: f32| (peak * f32::exp(-d * d / (2. * sigma * sigma))).min(1.);
+    (profile, 3. * sigma)
+}
+
+/// Splats the cross-section of a thread along the segment from `a` to `b`.
+/// Pixels around the ends are only covered by the part of them before the end.
+pub fn trace_thick_line<F: FnMut((u32, u32), f32)>(
+    mut a: (f32, f32),
+    mut b: (f32, f32),
+    (profile, extent): (impl Fn(f32) -> f32, f32),
+    mut pixel_callback: F,
+) {
+    let xy_inv = (b.1 - a.1).abs() > (b.0 - a.0).abs();
+    if xy_inv {
+        a = (a.1, a.0);
+        b = (b.1, b.0);
+    }
+
+    if a.0 > b.0 {
+        std::mem::swap(&mut a, &mut b);
+    }
+
+    let d = (b.0 - a.0, b.1 - a.1);
+    let length = f32::hypot(d.0, d.1);
+    if length == 0. {
+        return;
+    }
+
+    let u = (d.0 / length, d.1 / length);
+    let gradient = d.1 / d.0;
+    // The thread reaches further in the minor axis the steeper it is
+    let half_band = extent / u.0;
+
+    for x in (a.0 - extent).ceil() as i64..=(b.0 + extent).floor() as i64 {
+        let y_center = a.1 + (x as f32 - a.0) * gradient;
+
+        for y in (y_center - half_band).ceil() as i64..=(y_center + half_band).floor() as i64 {
+            if x < 0 || y < 0 {
+                continue;
+            }
+
+            let p = (x as f32 - a.0, y as f32 - a.1);
+            let along = p.0 * u.0 + p.1 * u.1;
+            let across = p.1 * u.0 - p.0 * u.1;
+
+            let along_coverage = f32::min(along + 0.5, length) - f32::max(along - 0.5, 0.);
+            let coverage = profile(across.abs()) * along_coverage.clamp(0., 1.);
+
+            if coverage > 0. {
+                let point = (x as u32, y as u32);
+                let point = if xy_inv { (point.1, point.0) } else { point };
+                pixel_callback(point, coverage);
+            }
+        }
+    }
+}
+
 #[test]
 fn tests() {
     let xiaolin_wu = |a: (f32, f32), b: (f32, f32)| {
@@ -129,3 +210,45 @@ fn tests() {
         xiaolin_wu((110.0, 170.0), (340.5, 290.77))
     );
 }
+
+#[test]
+fn thick_line_coverage() {
+    let total_coverage = |a: (f32, f32), b: (f32, f32), width: f32, gaussian: bool| {
+        let mut total = 0.;
+        if gaussian {
+            trace_thick_line(a, b, gaussian_profile(width), |_, c| total += c);
+        } else {
+            trace_thick_line(a, b, box_profile(width), |_, c| total += c);
+        }
+        total
+    };
+
+    for (a, b) in [
+        ((10.0, 10.0), (110.0, 10.0)),
+        ((20.0, 15.0), (120.5, 90.25)),
+        ((300.0, 40.0), (210.0, 250.0)),
+    ] {
+        let length = f32::hypot(b.0 - a.0, b.1 - a.1);
+        for width in [0.3, 1.0, 2.5, 6.0] {
+            for gaussian in [false, true] {
+                let coverage = total_coverage(a, b, width, gaussian);
+                assert!((coverage / (length * width) - 1.).abs() < 0.02);
+            }
+        }
+    }
+}
+
+#[test]
+fn line_set() {
+    let mut line_set = LineSet::new(600);
+    line_set.add(599, 3);
+    line_set.add(300, 256);
+    assert!(line_set.has(3, 599));
+    assert!(line_set.has(256, 300));
+    assert!(!line_set.has(3, 598));
+    assert!(!line_set.has(0, 256));
+
+    line_set.remove(3, 599);
+    assert!(!line_set.has(599, 3));
+    assert!(line_set.has(300, 256));
+}
@@ -0,0 +1,235 @@
+use super::*;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Densities of the canvas pixels and brightness of the target pixels
+/// modified by strings that are only being evaluated.
+#[derive(Clone, Default)]
+struct PixelOverlay {
+    density: HashMap<(u32, u32), f32>,
+    brightness: HashMap<(u32, u32), f32>,
+}
+
+impl PixelOverlay {
+    fn density(&self, coverage: &Coverage, x: u32, y: u32) -> f32 {
+        match self.density.get(&(x, y)) {
+            Some(density) => *density,
+            None => coverage.density(x, y),
+        }
+    }
+
+    fn brightness(&self, coverage: &Coverage, x: u32, y: u32) -> f32 {
+        match self.brightness.get(&(x, y)) {
+            Some(brightness) => *brightness,
+            None => coverage.brightness_sum(x, y),
+        }
+    }
+
+    /// Change of the brightness of the target pixels when drawing a string over the overlay,
+    /// along with the densities added to the canvas pixels.
+    fn string_changes(
+        &self,
+        coverage: &Coverage,
+        node_a: u16,
+        node_b: u16,
+        step: usize,
+        settings: &Settings,
+    ) -> (PixelChanges, PixelChanges) {
+        let mut density_changes = vec![];
+        settings.trace_string(node_a, node_b, step, |point, alpha| {
+            density_changes.push((point, string_density(alpha, settings)));
+        });
+        let brightness_changes = coverage
+            .brightness_changes(density_changes.clone(), |x, y| self.density(coverage, x, y));
+        (density_changes, brightness_changes)
+    }
+
+    fn draw_string(
+        &mut self,
+        coverage: &Coverage,
+        node_a: u16,
+        node_b: u16,
+        step: usize,
+        settings: &Settings,
+    ) {
+        let (density_changes, brightness_changes) =
+            self.string_changes(coverage, node_a, node_b, step, settings);
+
+        for ((x, y), change) in density_changes {
+            let density = self.density(coverage, x, y);
+            self.density.insert((x, y), density + change);
+        }
+        for ((x, y), change) in brightness_changes {
+            let brightness = self.brightness(coverage, x, y);
+            self.brightness.insert((x, y), brightness + change);
+        }
+    }
+}
+
+struct Beam {
+    nodes: Vec<u16>,
+    performance: i32,
+    overlay: PixelOverlay,
+}
+
+impl Beam {
+    fn last_node(&self, trace: &GreedySolver) -> u16 {
+        *self.nodes.last().or(trace.traced_nodes.last()).unwrap()
+    }
+
+    /// Node visited before the last one, which the path may not go straight back to.
+    fn previous_node(&self, trace: &GreedySolver) -> Option<u16> {
+        let nodes = trace.traced_nodes.iter().chain(&self.nodes);
+        nodes.rev().nth(1).copied()
+    }
+
+    /// Position in the path of the last node.
+    fn step(&self, trace: &GreedySolver) -> usize {
+        trace.traced_nodes.len() - 1 + self.nodes.len()
+    }
+
+    fn wraps(&self, trace: &GreedySolver, node: u16) -> u32 {
+        let beam_wraps = self.nodes.iter().filter(|&&n| n == node).count();
+        trace.wraps[node as usize] + beam_wraps as u32
+    }
+
+    fn has_line(&self, trace: &GreedySolver, a: u16, b: u16) -> bool {
+        if trace.line_set.has(a, b) {
+            return true;
+        }
+
+        let mut prev = *trace.traced_nodes.last().unwrap();
+        for &node in &self.nodes {
+            if (prev, node) == (a, b) || (prev, node) == (b, a) {
+                return true;
+            }
+            prev = node;
+        }
+        false
+    }
+}
+
+impl GreedySolver {
+    /// Beam search over sequences of `settings.lookahead` strings, keeping the
+    /// `settings.beam_width` (and at least `settings.sample_top_k`) best partial
+    /// sequences at each depth. Strings that do not improve the canvas are kept,
+    /// as the following ones may make up for them.
+    /// Returns the first node of the sequences found with the performance of the best
+    /// sequence that starts with it, from best to worst.
+    pub(super) fn beam_search(&self, image: &GrayImage, settings: &Settings) -> Vec<(i32, u16)> {
+        let beam_width = settings.beam_width.max(settings.sample_top_k).max(1);
+
+        let mut beams = vec![Beam {
+            nodes: vec![],
+            performance: 0,
+            overlay: PixelOverlay::default(),
+        }];
+
+        for _ in 0..settings.lookahead.max(1) {
+            let mut candidates: Vec<(i32, usize, u16)> = beams
+                .par_iter()
+                .enumerate()
+                .flat_map_iter(|(beam_index, beam)| {
+                    let node_index = beam.last_node(self);
+                    let previous_node = beam.previous_node(self);
+                    (0..settings.node_count())
+                        .filter(move |&next_idx| {
+                            settings.can_connect(node_index, next_idx)
+                                && Some(next_idx) != previous_node
+                                && settings.can_wrap(beam.wraps(self, next_idx))
+                                && !self.keep_out.blocks(
+                                    node_index,
+                                    next_idx,
+                                    beam.step(self),
+                                    settings,
+                                )
+                        })
+                        .filter_map(move |next_idx| {
+                            if !settings.repeat_lines && beam.has_line(self, node_index, next_idx) {
+                                return None;
+                            }
+
+                            let performance = string_performance(
+                                node_index,
+                                next_idx,
+                                beam.step(self),
+                                &self.coverage,
+                                &beam.overlay,
+                                image,
+                                settings,
+                            );
+                            Some((beam.performance + performance, beam_index, next_idx))
+                        })
+                })
+                .collect();
+
+            if candidates.is_empty() {
+                break;
+            }
+
+            // Ties are broken by the beam and node indices, so runs are reproducible
+            candidates
+                .sort_unstable_by(|a, b| (b.0.cmp(&a.0)).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+            candidates.truncate(beam_width);
+
+            beams = candidates
+                .into_iter()
+                .map(|(performance, beam_index, next_idx)| {
+                    let parent = &beams[beam_index];
+                    let node_index = parent.last_node(self);
+
+                    let mut overlay = parent.overlay.clone();
+                    overlay.draw_string(
+                        &self.coverage,
+                        node_index,
+                        next_idx,
+                        parent.step(self),
+                        settings,
+                    );
+
+                    let mut nodes = parent.nodes.clone();
+                    nodes.push(next_idx);
+
+                    Beam {
+                        nodes,
+                        performance,
+                        overlay,
+                    }
+                })
+                .collect();
+        }
+
+        let mut first_nodes: Vec<(i32, u16)> = vec![];
+        for beam in beams {
+            if let Some(&first) = beam.nodes.first() {
+                if !first_nodes.iter().any(|(_, node)| *node == first) {
+                    first_nodes.push((beam.performance, first));
+                }
+            }
+        }
+        first_nodes
+    }
+}
+
+/// Average reduction of the error per target pixel when drawing a string between two nodes,
+/// negative if the string makes the canvas worse.
+fn string_performance(
+    node_index: u16,
+    next_idx: u16,
+    step: usize,
+    coverage: &Coverage,
+    overlay: &PixelOverlay,
+    image: &GrayImage,
+    settings: &Settings,
+) -> i32 {
+    let (_, changes) = overlay.string_changes(coverage, node_index, next_idx, step, settings);
+    let count = changes.len() as i64 + 1;
+    let performance = -coverage.error_change(
+        &changes,
+        |x, y| overlay.brightness(coverage, x, y),
+        image,
+        settings,
+    );
+
+    (performance / count) as i32
+}
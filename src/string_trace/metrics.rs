@@ -0,0 +1,41 @@
+use super::*;
+use std::fmt;
+use std::time::Duration;
+
+/// Measurements used to compare the results of the different solvers.
+pub struct SolverMetrics {
+    pub solver: &'static str,
+    pub lines: usize,
+    pub error: i64,
+    /// Error per pixel of the target image
+    pub mean_error: f64,
+    pub elapsed: Duration,
+}
+
+impl SolverMetrics {
+    pub fn measure(
+        solver: &dyn Solver,
+        image: &GrayImage,
+        settings: &Settings,
+        elapsed: Duration,
+    ) -> Self {
+        let error = solver.coverage().error(image, settings);
+        Self {
+            solver: solver.name(),
+            lines: solver.sequence().len().saturating_sub(1),
+            error,
+            mean_error: error as f64 / (image.width() * image.height()) as f64,
+            elapsed,
+        }
+    }
+}
+
+impl fmt::Display for SolverMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} solver: {} lines, error {} ({:.2} per pixel) in {:.2?}",
+            self.solver, self.lines, self.error, self.mean_error, self.elapsed,
+        )
+    }
+}
@@ -1,11 +1,31 @@
+mod coverage;
+mod euler;
+mod greedy;
+mod keep_out;
+mod least_squares;
 mod line;
+mod lookahead;
+mod metrics;
+mod refine;
+mod rng;
+mod solver;
 
 use crate::*;
 use bevy::render::render_resource::*;
+pub use coverage::*;
+use euler::*;
+use greedy::*;
 use image::{GrayImage, Luma};
+pub use keep_out::*;
+use least_squares::*;
+pub use line::string_density;
 use line::*;
-use rayon::prelude::*;
+pub use metrics::*;
+use refine::*;
+use rng::*;
+pub use solver::*;
 use std::f32::consts::PI;
+use std::time::Instant;
 
 pub struct StringTracePlugin;
 
@@ -17,19 +37,41 @@ impl Plugin for StringTracePlugin {
 
 #[derive(Component)]
 pub struct StringTrace {
-    pub traced_nodes: Vec<u16>,
+    pub solver: Box<dyn Solver>,
     pub canvas: Handle<Image>,
-    pub line_set: LineSet,
-    pub done: bool,
+    /// Number of nodes the solver was created with
+    node_count: u16,
+    started: Instant,
 }
 
+#[allow(clippy::type_complexity)]
 fn update_string_trace(
-    mut query: Query<(&mut StringTrace, &mut UiImage, &Settings), Changed<ProcessedImage>>,
+    mut query: Query<
+        (
+            &mut StringTrace,
+            &mut UiImage,
+            &ProcessedImage,
+            &WeightMap,
+            &KeepOutMask,
+            &Settings,
+        ),
+        Or<(
+            Changed<ProcessedImage>,
+            Changed<WeightMap>,
+            Changed<KeepOutMask>,
+        )>,
+    >,
     mut assets: ResMut<Assets<Image>>,
 ) {
-    for (mut trace, mut image, settings) in &mut query {
+    for (mut trace, mut image, target_image, weight_map, keep_out_mask, settings) in &mut query {
         *trace = StringTrace::new(settings, &mut assets);
         image.texture = trace.canvas.clone();
+
+        if let Some(target_image) = &target_image.0 {
+            let weights = weight_map.active();
+            let keep_out = KeepOut::new(keep_out_mask.image.as_ref(), settings);
+            trace.solver.init(target_image, weights, keep_out, settings);
+        }
     }
 }
 
@@ -40,13 +82,26 @@ fn trace_string(
     for (mut trace, target_image, settings) in &mut query {
         // if tracker
         if let Some(target_image) = &target_image.0 {
-            if target_image.width() == settings.diameter {
-                for _ in 0..128 {
-                    if trace.done {
+            if target_image.width() == settings.diameter
+                && trace.is_current(settings)
+                && !trace.solver.is_done()
+            {
+                for _ in 0..trace.solver.steps_per_frame(settings) {
+                    if trace.solver.is_done() {
                         break;
                     }
 
-                    trace.trace_best(target_image, settings, &mut assets);
+                    trace.solver.step(target_image, settings);
+                }
+
+                let mut canvas = MutImage::from_handle(&trace.canvas, &mut assets).unwrap();
+                trace.solver.coverage().render(&mut canvas, settings);
+
+                if trace.solver.is_done() {
+                    let elapsed = trace.started.elapsed();
+                    let metrics =
+                        SolverMetrics::measure(&*trace.solver, target_image, settings, elapsed);
+                    info!("{}", metrics);
                 }
             }
         }
@@ -62,113 +117,258 @@ impl StringTrace {
                 depth_or_array_layers: 1,
             },
             TextureDimension::D2,
-            &[255],
-            TextureFormat::R8Unorm,
+            &settings.composite(1.),
+            TextureFormat::Rgba8UnormSrgb,
         );
         Self {
-            traced_nodes: vec![0],
-            // canvas: GrayImage::from_pixel(settings.diameter, settings.diameter, Luma([255])),
+            solver: settings.solver.create(settings),
             canvas: assets.add(image),
-            line_set: LineSet::new(),
-            done: false,
+            node_count: settings.node_count(),
+            started: Instant::now(),
         }
     }
 
-    fn trace_best(
-        &mut self,
-        target_image: &GrayImage,
-        settings: &Settings,
-        assets: &mut Assets<Image>,
-    ) {
-        let Some(next_node_index) = self.best_next_string(target_image, settings, &assets) else {
-            self.done = true;
-            return;
-        };
+    /// Whether the nodes of the sequence are the nodes of the settings, which may
+    /// change a few frames before the trace is restarted.
+    pub fn is_current(&self, settings: &Settings) -> bool {
+        self.node_count == settings.node_count()
+    }
+}
 
-        let node_index = *self.traced_nodes.last().unwrap();
-        let mut canvas = MutImage::from_handle(&self.canvas, assets).unwrap();
-        trace_line(
-            settings.node_pos(node_index),
-            settings.node_pos(next_node_index),
-            |point, alpha| {
-                let pixel = canvas.get_pixel_mut(point.0, point.1);
-                *pixel = overlay_string(*pixel, alpha, settings);
-            },
-        );
+/// Perceived brightness of a color.
+pub fn luminance([r, g, b]: [u8; 3]) -> f32 {
+    0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32
+}
 
-        if !settings.repeat_lines {
-            self.line_set.add(node_index, next_node_index);
+impl Settings {
+    pub fn color_dist(&self, a: Luma<u8>, b: Luma<u8>) -> i32 {
+        let r = a.0[0] as i32 - b.0[0] as i32;
+        match &self.color_distance_fn {
+            ColorDistanceFn::ABS => r.abs(),
+            ColorDistanceFn::SQUARE => r * r,
         }
+    }
 
-        self.traced_nodes.push(next_node_index);
-
-        if self.traced_nodes.len() > settings.max_lines {
-            self.done = true;
+    /// Fraction of the board that the strings should let through to match the brightness
+    /// of the target. When the thread is lighter than the board, the tones are inverted.
+    pub fn target_transmittance(&self, brightness: u8) -> f32 {
+        let board = luminance(self.board_color.rgb());
+        let thread = luminance(self.thread_color.rgb());
+        if board == thread {
+            return 1.;
         }
+        ((brightness as f32 - thread) / (board - thread)).clamp(0., 1.)
     }
 
-    fn best_next_string(
-        &self,
-        image: &GrayImage,
-        settings: &Settings,
-        assets: &Assets<Image>,
-    ) -> Option<u16> {
-        let node_index = *self.traced_nodes.last().unwrap();
-        let origin = settings.node_pos(node_index);
-        let canvas = RefImage::from_handle(&self.canvas, assets).unwrap();
-
-        (0..settings.nodes - 1)
-            .into_par_iter()
-            .filter_map(|i| {
-                let next_idx = if i < node_index { i } else { i + 1 };
-
-                if !settings.repeat_lines && self.line_set.has(node_index, next_idx) {
-                    return None;
-                }
+    /// Brightness of the target for which the strings should let `transmittance`
+    /// of the board through, the inverse of `target_transmittance`.
+    pub fn target_brightness(&self, transmittance: f32) -> f32 {
+        let board = luminance(self.board_color.rgb());
+        let thread = luminance(self.thread_color.rgb());
+        thread + (board - thread) * transmittance
+    }
 
-                let mut performance = 0;
-                let mut count = 1;
+    /// Color of a pixel where the strings let `transmittance` of the board through.
+    pub fn composite(&self, transmittance: f32) -> [u8; 4] {
+        let board = self.board_color.rgb();
+        let thread = self.thread_color.rgb();
+        let channel = |i: usize| {
+            let color = thread[i] as f32 + (board[i] as f32 - thread[i] as f32) * transmittance;
+            color.round() as u8
+        };
+        [channel(0), channel(1), channel(2), 255]
+    }
 
-                trace_line(origin, settings.node_pos(next_idx), |point, alpha| {
-                    let target = *image.get_pixel(point.0, point.1);
-                    let pixel = canvas.get_pixel(point.0, point.1);
-                    let new_pixel = overlay_string(pixel, alpha, settings);
+    /// Pixels of the canvas for each millimeter of the board.
+    pub fn pixels_per_mm(&self) -> f32 {
+        self.diameter as f32 / self.board_diameter_mm
+    }
 
-                    let pixel_err = settings.color_dist(target, Luma([pixel]));
-                    let new_pixel_err = settings.color_dist(target, Luma([new_pixel]));
+    /// Width of the thread in pixels of the canvas.
+    pub fn thread_width(&self) -> f32 {
+        self.thread_thickness_mm * self.pixels_per_mm()
+    }
 
-                    performance += pixel_err - new_pixel_err;
-                    count += 1;
-                });
+    fn nail_radius(&self) -> f32 {
+        self.nail_diameter_mm * self.pixels_per_mm() / 2.
+    }
 
-                if performance > 0 {
-                    Some((performance / count, next_idx))
-                } else {
-                    None
+    /// Sets of nails on the board: the concentric rings, from the outer one inwards,
+    /// followed by the clusters. Sets whose nails would not all have a `u16` index are left out.
+    pub fn nail_sets(&self) -> impl Iterator<Item = NailRing> + '_ {
+        let rings = (0..self.rings.max(1))
+            .map(|ring| {
+                let radius = 1. - ring as f32 * self.ring_spacing;
+                NailRing {
+                    center: (0., 0.),
+                    radius,
+                    nails: ((self.nodes as f32 * radius).round() as u16).max(1),
                 }
             })
-            .max_by_key(|(performance, _)| *performance)
-            .map(|(_, next_node_index)| next_node_index)
+            .take_while(|ring| ring.radius > 0.);
+        rings
+            .chain(self.clusters.iter().copied())
+            .scan(0u16, |count, set| {
+                *count = count.checked_add(set.nails)?;
+                Some(set)
+            })
     }
-}
 
-impl Settings {
-    fn color_dist(&self, a: Luma<u8>, b: Luma<u8>) -> i32 {
-        let r = a.0[0] as i32 - b.0[0] as i32;
-        match &self.color_distance_fn {
-            ColorDistanceFn::ABS => r.abs(),
-            ColorDistanceFn::SQUARE => r * r,
+    /// Total number of nails of all the sets, which `nail_sets` keeps within a `u16`.
+    pub fn node_count(&self) -> u16 {
+        self.nail_sets().map(|set| set.nails).sum()
+    }
+
+    /// Index of the set of a node, the set, and the index of the node in the set.
+    /// Returns `None` for a node past the last set, as found in a sequence traced
+    /// before the number of nodes changed.
+    fn locate(&self, node_index: u16) -> Option<(usize, NailRing, u16)> {
+        let mut first = 0;
+        for (set_index, set) in self.nail_sets().enumerate() {
+            if node_index < first + set.nails {
+                return Some((set_index, set, node_index - first));
+            }
+            first += set.nails;
         }
+        None
     }
 
-    fn node_pos(&self, node_index: u16) -> (f32, f32) {
-        let angle_step = 2. * PI / self.nodes as f32;
+    /// Whether two nodes may be joined by a string: their sets must be allowed to
+    /// connect, and nodes of the same set must be far enough apart along it.
+    fn can_connect(&self, node_a: u16, node_b: u16) -> bool {
+        let (Some((set_a, set, index_a)), Some((set_b, _, index_b))) =
+            (self.locate(node_a), self.locate(node_b))
+        else {
+            return false;
+        };
+
+        let sets_connect = match self.ring_connections {
+            RingConnections::All => true,
+            RingConnections::ToOuter => set_a == 0 || set_b == 0,
+            RingConnections::Neighbours => set_a.abs_diff(set_b) <= 1,
+        };
+        if set_a != set_b {
+            return sets_connect;
+        }
+
+        let distance = index_a.abs_diff(index_b);
+        let distance = distance.min(set.nails - distance);
+        sets_connect && distance >= self.min_nail_skip.max(1)
+    }
+
+    /// Whether a nail that already has `wraps` wraps can take one more.
+    fn can_wrap(&self, wraps: u32) -> bool {
+        self.max_wraps_per_nail == 0 || wraps < self.max_wraps_per_nail
+    }
+
+    /// Position of a node on the target image. Nodes that are not on the board
+    /// are placed at its center.
+    pub fn node_pos(&self, node_index: u16) -> (f32, f32) {
+        let center = (self.diameter - 2) as f32 / 2.;
+        let Some((_, set, index)) = self.locate(node_index) else {
+            return (center, center);
+        };
+        let angle_step = 2. * PI / set.nails as f32;
 
-        let mut angle = angle_step * node_index as f32;
-        angle += f32::cos(node_index as f32) * angle_step * self.nodes_offset;
+        let mut angle = angle_step * index as f32;
+        angle += f32::cos(index as f32) * angle_step * self.nodes_offset;
 
         let (s, c) = f32::sin_cos(angle);
-        let r = (self.diameter - 2) as f32 / 2.;
-        (r + r * c, r + r * s)
+        let board_radius = center - self.nail_radius();
+        let set_center = (
+            center + set.center.0 * board_radius,
+            center + set.center.1 * board_radius,
+        );
+        let r = set.radius * board_radius;
+        (set_center.0 + r * c, set_center.1 + r * s)
+    }
+
+    /// 1 if the nail stays on the left of the thread at a position of the path, -1 if on the right.
+    fn wrap_side(&self, step: usize) -> f32 {
+        match self.wrap_direction.at(step) {
+            WrapDirection::Clockwise => -1.,
+            _ => 1.,
+        }
+    }
+
+    /// Points where a string leaves `node_a` and reaches `node_b`, tangent to both nails.
+    /// `step` is the position of `node_a` in the path, which sets the wrap directions.
+    pub fn string_ends(&self, node_a: u16, node_b: u16, step: usize) -> ((f32, f32), (f32, f32)) {
+        let a = self.node_pos(node_a);
+        let b = self.node_pos(node_b);
+        let radius = self.nail_radius();
+
+        let d = (b.0 - a.0, b.1 - a.1);
+        let length = f32::hypot(d.0, d.1);
+        if radius <= 0. || length <= 2. * radius {
+            return (a, b);
+        }
+
+        let u = (d.0 / length, d.1 / length);
+        let v = (-u.1, u.0);
+
+        // Normal of the tangent line, rotated from `v` when the string crosses between the nails
+        let side_a = self.wrap_side(step);
+        let side_b = self.wrap_side(step + 1);
+        let sin = (side_a - side_b) * radius / length;
+        let cos = (1. - sin * sin).sqrt();
+        let n = (cos * v.0 + sin * u.0, cos * v.1 + sin * u.1);
+
+        (
+            (a.0 + side_a * radius * n.0, a.1 + side_a * radius * n.1),
+            (b.0 + side_b * radius * n.0, b.1 + side_b * radius * n.1),
+        )
+    }
+
+    /// Calls `pixel_callback` on every pixel covered by a string of the path.
+    pub fn trace_string<F: FnMut((u32, u32), f32)>(
+        &self,
+        node_a: u16,
+        node_b: u16,
+        step: usize,
+        pixel_callback: F,
+    ) {
+        let (a, b) = self.string_ends(node_a, node_b, step);
+        self.rasterize(a, b, pixel_callback);
+    }
+
+    /// Size in pixels of the canvas the solvers draw on.
+    pub fn canvas_size(&self) -> u32 {
+        self.diameter * self.supersampling.max(1)
+    }
+
+    /// Calls `pixel_callback` with the fraction of each canvas pixel covered by a thread
+    /// from `a` to `b`, using the configured rasterizer.
+    /// `a` and `b` are given in pixels of the target image.
+    pub fn rasterize<F: FnMut((u32, u32), f32)>(
+        &self,
+        a: (f32, f32),
+        b: (f32, f32),
+        mut pixel_callback: F,
+    ) {
+        // Pixel centers of the target image are at the center of the canvas pixels they cover
+        let scale = self.supersampling.max(1) as f32;
+        let offset = (scale - 1.) / 2.;
+        let a = (a.0 * scale + offset, a.1 * scale + offset);
+        let b = (b.0 * scale + offset, b.1 * scale + offset);
+
+        let width = self.thread_width() * scale;
+        let size = self.canvas_size();
+        let mut clipped_callback = |point: (u32, u32), coverage: f32| {
+            if point.0 < size && point.1 < size {
+                pixel_callback(point, coverage);
+            }
+        };
+
+        match self.rasterizer {
+            // Xiaolin Wu covers about one pixel across, so thinner threads are dimmed
+            Rasterizer::XiaolinWu => trace_line(a, b, |point, alpha| {
+                clipped_callback(point, alpha * width.min(1.))
+            }),
+            Rasterizer::Gaussian => {
+                trace_thick_line(a, b, gaussian_profile(width), clipped_callback)
+            }
+            Rasterizer::Box => trace_thick_line(a, b, box_profile(width), clipped_callback),
+        }
     }
 }
@@ -0,0 +1,158 @@
+use super::*;
+use rayon::prelude::*;
+
+/// State of the post-optimization pass that revisits the traced strings.
+pub struct Refinement {
+    pub iteration: usize,
+    pub initial_error: i64,
+    pub error: i64,
+    pub accepted: usize,
+    rng: Rng,
+}
+
+impl Refinement {
+    pub fn new(error: i64, seed: u64) -> Self {
+        Self {
+            iteration: 0,
+            initial_error: error,
+            error,
+            accepted: 0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    fn report(&self) {
+        let improvement =
+            100. * (self.initial_error - self.error) as f64 / self.initial_error.max(1) as f64;
+        info!(
+            "Refinement accepted {} of {} moves, error {} -> {} ({:.2}% lower)",
+            self.accepted, self.iteration, self.initial_error, self.error, improvement,
+        );
+    }
+}
+
+impl GreedySolver {
+    /// Picks a random node of the traced path and replaces it with the node that
+    /// reduces the error the most, or removes it from the path altogether.
+    /// The path stays continuous because both strings touching the node are replaced.
+    ///
+    /// Moves that increase the error are accepted with a probability that decreases
+    /// as the iterations go on (simulated annealing). With a temperature of 0 only
+    /// improvements are accepted (hill climbing).
+    pub(super) fn refine(&mut self, image: &GrayImage, settings: &Settings) {
+        let Some(mut refinement) = self.refinement.take() else {
+            return;
+        };
+
+        if refinement.iteration >= settings.refine_iterations || self.traced_nodes.len() < 2 {
+            refinement.report();
+            self.refinement = Some(refinement);
+            self.done = true;
+            return;
+        }
+
+        let position = 1 + refinement.rng.below(self.traced_nodes.len() - 1);
+        let prev = self.traced_nodes[position - 1];
+        let node = self.traced_nodes[position];
+        let next = self.traced_nodes.get(position + 1).copied();
+        let before = position.checked_sub(2).map(|i| self.traced_nodes[i]);
+        let after = self.traced_nodes.get(position + 2).copied();
+
+        let mut removed = vec![(prev, node, position - 1)];
+        removed.extend(next.map(|next| (node, next, position)));
+
+        // Removing a node shifts the rest of the path, which flips the side of every
+        // following nail when the wrap direction alternates
+        let can_remove = next.is_none()
+            || settings.nail_diameter_mm <= 0.
+            || settings.wrap_direction != WrapDirection::Alternate;
+
+        // `nodes` stands for removing the node from the path
+        let nodes = settings.node_count();
+        let best = (0..=nodes)
+            .into_par_iter()
+            .filter_map(|replacement| {
+                let added = if replacement == nodes {
+                    match next {
+                        _ if !can_remove => return None,
+                        Some(next) if next == prev => return None,
+                        Some(next) => vec![(prev, next, position - 1)],
+                        None => vec![],
+                    }
+                } else {
+                    if replacement == node || replacement == prev || Some(replacement) == next {
+                        return None;
+                    }
+                    if !settings.can_wrap(self.wraps[replacement as usize]) {
+                        return None;
+                    }
+                    let mut added = vec![(prev, replacement, position - 1)];
+                    added.extend(next.map(|next| (replacement, next, position)));
+                    added
+                };
+
+                if !settings.repeat_lines && added.iter().any(|&(a, b, _)| self.line_set.has(a, b))
+                {
+                    return None;
+                }
+
+                if !added.iter().all(|&(a, b, step)| {
+                    settings.can_connect(a, b) && !self.keep_out.blocks(a, b, step, settings)
+                }) {
+                    return None;
+                }
+
+                // The path may not go straight back to the node it comes from
+                let window: Vec<u16> = before
+                    .into_iter()
+                    .chain([prev])
+                    .chain((replacement != nodes).then_some(replacement))
+                    .chain(next)
+                    .chain(after)
+                    .collect();
+                if window.windows(3).any(|nodes| nodes[0] == nodes[2]) {
+                    return None;
+                }
+
+                let delta = self.coverage.error_delta(&removed, &added, image, settings);
+                Some((delta, replacement, added))
+            })
+            .min_by_key(|(delta, replacement, _)| (*delta, *replacement));
+
+        let progress = refinement.iteration as f32 / settings.refine_iterations as f32;
+        let temperature = settings.refine_temperature * (1. - progress);
+        refinement.iteration += 1;
+
+        if let Some((delta, replacement, added)) = best {
+            let accept = delta < 0
+                || temperature > 0.
+                    && refinement.rng.next_f32() < f32::exp(-(delta as f32) / temperature);
+
+            if accept {
+                for &(a, b, step) in &removed {
+                    self.draw_string(a, b, step, -1., settings);
+                    self.line_set.remove(a, b);
+                }
+                for &(a, b, step) in &added {
+                    self.draw_string(a, b, step, 1., settings);
+                    if !settings.repeat_lines {
+                        self.line_set.add(a, b);
+                    }
+                }
+
+                self.wraps[node as usize] -= 1;
+                if replacement == nodes {
+                    self.traced_nodes.remove(position);
+                } else {
+                    self.traced_nodes[position] = replacement;
+                    self.wraps[replacement as usize] += 1;
+                }
+
+                refinement.error += delta;
+                refinement.accepted += 1;
+            }
+        }
+
+        self.refinement = Some(refinement);
+    }
+}
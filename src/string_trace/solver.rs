@@ -0,0 +1,57 @@
+use super::*;
+
+/// Algorithm that approximates the target image with a continuous path of strings.
+pub trait Solver: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Resets the solver to start approximating a new target image,
+    /// optionally weighting the error of each pixel by a weight map.
+    /// Strings may not cross the `keep_out` areas.
+    fn init(
+        &mut self,
+        image: &GrayImage,
+        weights: Option<&GrayImage>,
+        keep_out: KeepOut,
+        settings: &Settings,
+    );
+
+    /// Advances the solver by one iteration.
+    fn step(&mut self, image: &GrayImage, settings: &Settings);
+
+    fn is_done(&self) -> bool;
+
+    /// Nodes visited by the path of strings found so far.
+    fn sequence(&self) -> &[u16];
+
+    /// Canvas with the strings of the current sequence drawn.
+    fn coverage(&self) -> &Coverage;
+
+    /// Number of iterations to run on every frame.
+    fn steps_per_frame(&self, _settings: &Settings) -> usize {
+        128
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SolverKind {
+    Greedy,
+    LeastSquares,
+}
+
+impl SolverKind {
+    /// Solver with the name used on the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "greedy" => Some(SolverKind::Greedy),
+            "least-squares" => Some(SolverKind::LeastSquares),
+            _ => None,
+        }
+    }
+
+    pub fn create(&self, settings: &Settings) -> Box<dyn Solver> {
+        match self {
+            SolverKind::Greedy => Box::new(GreedySolver::new(settings)),
+            SolverKind::LeastSquares => Box::new(LeastSquaresSolver::new(settings)),
+        }
+    }
+}
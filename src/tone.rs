@@ -0,0 +1,342 @@
+use crate::*;
+use bevy::render::render_resource::*;
+use image::{GrayImage, Luma};
+use imageproc::contrast::equalize_histogram;
+use imageproc::filter::gaussian_blur_f32;
+use std::f32::consts::PI;
+use std::fmt;
+
+/// Number of tiles along each side of the image for the adaptive equalization.
+const CLAHE_TILES: u32 = 8;
+
+/// Applies the tone adjustments of the settings, one stage after the other:
+/// levels, brightness and contrast, gamma, equalization and sharpening.
+pub fn adjust_tones(image: &mut GrayImage, settings: &Settings) {
+    apply_levels(image, settings);
+
+    match settings.equalization {
+        Equalization::Off => {}
+        Equalization::Histogram => *image = equalize_histogram(image),
+        Equalization::Adaptive => *image = clahe(image, settings.clahe_clip_limit),
+    }
+
+    if settings.sharpen_amount > 0. {
+        *image = unsharp_mask(image, settings.sharpen_radius, settings.sharpen_amount);
+    }
+}
+
+/// Value of each pixel after the levels, brightness, contrast and gamma.
+fn levels_lookup(settings: &Settings) -> Vec<u8> {
+    let black = settings.black_point as f32;
+    let white = (settings.white_point as f32).max(black + 1.);
+
+    (0..=255)
+        .map(|value| {
+            let mut value = ((value as f32 - black) / (white - black)).clamp(0., 1.);
+            value = (value - 0.5) * settings.contrast + 0.5 + settings.brightness;
+            value = value.clamp(0., 1.).powf(settings.gamma.max(0.01));
+            (value * 255.).round() as u8
+        })
+        .collect()
+}
+
+/// Levels, brightness, contrast and gamma, which only depend on the value of each pixel.
+fn apply_levels(image: &mut GrayImage, settings: &Settings) {
+    let lookup = levels_lookup(settings);
+    for pixel in image.pixels_mut() {
+        pixel.0[0] = lookup[pixel.0[0] as usize];
+    }
+}
+
+/// Contrast limited adaptive histogram equalization: each tile is equalized with a
+/// histogram clipped at `clip_limit` times its mean, and the mappings of the
+/// neighbouring tiles are interpolated.
+fn clahe(image: &GrayImage, clip_limit: f32) -> GrayImage {
+    let (width, height) = image.dimensions();
+    let tile_width = width.div_ceil(CLAHE_TILES).max(1);
+    let tile_height = height.div_ceil(CLAHE_TILES).max(1);
+
+    let mut mappings = vec![[0u8; 256]; (CLAHE_TILES * CLAHE_TILES) as usize];
+    for tile_y in 0..CLAHE_TILES {
+        for tile_x in 0..CLAHE_TILES {
+            let mut histogram = [0u32; 256];
+            for y in tile_y * tile_height..((tile_y + 1) * tile_height).min(height) {
+                for x in tile_x * tile_width..((tile_x + 1) * tile_width).min(width) {
+                    histogram[image.get_pixel(x, y).0[0] as usize] += 1;
+                }
+            }
+
+            let count: u32 = histogram.iter().sum();
+            let mapping = &mut mappings[(tile_x + tile_y * CLAHE_TILES) as usize];
+            if count == 0 {
+                for (value, mapped) in mapping.iter_mut().enumerate() {
+                    *mapped = value as u8;
+                }
+                continue;
+            }
+
+            // The clipped counts are spread over the whole histogram
+            let limit = ((clip_limit * count as f32 / 256.) as u32).max(1);
+            let mut excess = 0;
+            for bin in &mut histogram {
+                excess += bin.saturating_sub(limit);
+                *bin = (*bin).min(limit);
+            }
+            let bonus = excess / 256;
+            let total: u32 = histogram.iter().sum::<u32>() + bonus * 256;
+
+            let mut cumulative = 0;
+            for (bin, mapped) in histogram.iter().zip(mapping.iter_mut()) {
+                cumulative += bin + bonus;
+                *mapped = (cumulative as u64 * 255 / total as u64) as u8;
+            }
+        }
+    }
+
+    // Position of a pixel between the centers of the tiles
+    let tile_position = |coordinate: u32, tile_size: u32| {
+        let position = (coordinate as f32 + 0.5) / tile_size as f32 - 0.5;
+        let position = position.clamp(0., (CLAHE_TILES - 1) as f32);
+        let first = position.floor() as u32;
+        (
+            first,
+            (first + 1).min(CLAHE_TILES - 1),
+            position - first as f32,
+        )
+    };
+
+    GrayImage::from_fn(width, height, |x, y| {
+        let value = image.get_pixel(x, y).0[0] as usize;
+        let (x0, x1, tx) = tile_position(x, tile_width);
+        let (y0, y1, ty) = tile_position(y, tile_height);
+        let mapped = |tile_x: u32, tile_y: u32| {
+            mappings[(tile_x + tile_y * CLAHE_TILES) as usize][value] as f32
+        };
+
+        let top = mapped(x0, y0) * (1. - tx) + mapped(x1, y0) * tx;
+        let bottom = mapped(x0, y1) * (1. - tx) + mapped(x1, y1) * tx;
+        Luma([(top * (1. - ty) + bottom * ty).round() as u8])
+    })
+}
+
+/// Sharpens the image by adding `amount` times its difference with a blurred copy.
+fn unsharp_mask(image: &GrayImage, radius: f32, amount: f32) -> GrayImage {
+    let blurred = gaussian_blur_f32(image, radius.max(0.1));
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        let value = image.get_pixel(x, y).0[0] as f32;
+        let blurred = blurred.get_pixel(x, y).0[0] as f32;
+        Luma([(value + amount * (value - blurred)).round().clamp(0., 255.) as u8])
+    })
+}
+
+pub struct TonePlugin;
+
+impl Plugin for TonePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update_target_preview);
+    }
+}
+
+/// Viewport of the processed target shown next to the result in the side-by-side view.
+#[derive(Component)]
+struct TargetPreview;
+
+pub fn spawn_target_preview(parent: &mut ChildBuilder, assets: &mut Assets<Image>) {
+    parent
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    width: Val::Auto,
+                    height: Val::Percent(100.),
+                    aspect_ratio: Some(1.),
+                    overflow: Overflow::clip(),
+                    ..default()
+                },
+                ..default()
+            },
+            TargetPreview,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                ImageBundle {
+                    style: result_style(),
+                    image: UiImage::new(assets.add(Image::default())),
+                    ..default()
+                },
+                FollowsResultView,
+            ));
+        });
+}
+
+/// Image of the target as it would look with the board and thread colors.
+pub fn target_image(target: &GrayImage, settings: &Settings) -> Image {
+    let data = target
+        .pixels()
+        .flat_map(|pixel| settings.composite(pixel.0[0] as f32 / 255.))
+        .collect();
+    Image::new(
+        Extent3d {
+            width: target.width(),
+            height: target.height(),
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+fn update_target_preview(
+    trace_query: Query<(Ref<ProcessedImage>, Ref<ViewSettings>, &Settings)>,
+    mut preview_query: Query<(&mut Style, &Children), With<TargetPreview>>,
+    image_query: Query<&UiImage>,
+    mut assets: ResMut<Assets<Image>>,
+) {
+    let Ok((target, view_settings, settings)) = trace_query.get_single() else {
+        return;
+    };
+    if !target.is_changed() && !view_settings.is_changed() {
+        return;
+    }
+
+    let shown = view_settings.view_mode == ViewMode::SideBySide;
+    for (mut style, children) in &mut preview_query {
+        style.display = if shown { Display::Flex } else { Display::None };
+
+        if let (true, Some(target)) = (shown, &target.0) {
+            for image in image_query.iter_many(children) {
+                if let Some(image) = assets.get_mut(&image.texture) {
+                    *image = target_image(target, settings);
+                }
+            }
+        }
+    }
+}
+
+/// Tone mapping chosen by the automatic calibration, as the settings that give about
+/// the same result without it. The levels are exact when the brightness, contrast and
+/// gamma are neutral and there is no equalization or sharpening.
+pub struct ToneCalibration {
+    pub black_point: u8,
+    pub white_point: u8,
+    /// Opacity of the strings that makes up for the densities of the target being scaled
+    pub string_alpha: f32,
+    /// Mean density of the target over the board, after stretching
+    pub target_density: f32,
+    /// Mean density the strings can reach over the board
+    pub achievable_density: f32,
+}
+
+impl fmt::Display for ToneCalibration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "auto tone: like black_point {}, white_point {}, darken 0 and string_alpha {:.2} \
+            (mean density {:.3} for {:.3} achievable)",
+            self.black_point,
+            self.white_point,
+            self.string_alpha,
+            self.target_density,
+            self.achievable_density
+        )
+    }
+}
+
+/// Source values that the levels map to `low` and `high` after darkening, or the other way
+/// around, which become the black and white points of the same tones.
+fn equivalent_levels(low: f32, high: f32, settings: &Settings) -> (u8, u8) {
+    let lookup = levels_lookup(settings);
+    let source_value = |leveled: f32| {
+        let leveled = (leveled + settings.darken as f32).round().min(255.) as u8;
+        lookup
+            .iter()
+            .position(|&value| value >= leveled)
+            .unwrap_or(255) as u8
+    };
+
+    let (low, high) = (source_value(low), source_value(high));
+    let black_point = low.min(high);
+    (
+        black_point,
+        low.max(high).max(black_point.saturating_add(1)),
+    )
+}
+
+/// Stretches the transmittances of the target over the board to the full range,
+/// then lightens it until its mean density is reachable with `max_lines` strings.
+pub fn calibrate_tones(target: &mut GrayImage, settings: &Settings) -> ToneCalibration {
+    let center = target.width() as f32 / 2.;
+    let on_board =
+        |x: u32, y: u32| f32::hypot(x as f32 + 0.5 - center, y as f32 + 0.5 - center) <= center;
+
+    let mut histogram = [0u32; 256];
+    for (x, y, pixel) in target.enumerate_pixels() {
+        if on_board(x, y) {
+            histogram[pixel.0[0] as usize] += 1;
+        }
+    }
+    let count: u32 = histogram.iter().sum();
+    let percentile = |fraction: f32| {
+        let mut cumulative = 0;
+        for (value, bin) in histogram.iter().enumerate() {
+            cumulative += bin;
+            if cumulative as f32 >= fraction * count as f32 {
+                return value as u8;
+            }
+        }
+        255
+    };
+    let low = percentile(0.01).min(254);
+    let high = percentile(0.99).max(low + 1);
+
+    let stretch =
+        |value: u8| ((value as f32 - low as f32) / (high as f32 - low as f32)).clamp(0., 1.);
+    let target_density = target
+        .enumerate_pixels()
+        .filter(|(x, y, _)| on_board(*x, *y))
+        .map(|(_, _, pixel)| -stretch(pixel.0[0]).max(1. / 255.).ln())
+        .sum::<f32>()
+        / count.max(1) as f32;
+
+    let achievable_density = achievable_density(settings);
+    let density_scale = if target_density > achievable_density {
+        achievable_density / target_density
+    } else {
+        1.
+    };
+
+    for pixel in target.pixels_mut() {
+        let transmittance = stretch(pixel.0[0]).powf(density_scale);
+        pixel.0[0] = (transmittance * 255.).round() as u8;
+    }
+
+    let (black_point, white_point) = equivalent_levels(
+        settings.target_brightness(low as f32 / 255.),
+        settings.target_brightness(high as f32 / 255.),
+        settings,
+    );
+
+    // Strings that are 1 / density_scale times denser reach the unscaled densities
+    let transmittance = 1. - settings.string_alpha.min(0.99);
+    let string_alpha = 1. - transmittance.powf(1. / density_scale);
+
+    ToneCalibration {
+        black_point,
+        white_point,
+        string_alpha,
+        target_density,
+        achievable_density,
+    }
+}
+
+/// Mean density added over the board by `max_lines` strings of average length,
+/// which is 4/π times the radius for chords between random points of the rim.
+fn achievable_density(settings: &Settings) -> f32 {
+    let radius = settings.diameter as f32 / 2.;
+    let width = settings.thread_width();
+    let density_per_length = string_density(width.min(1.), settings) * width.max(1.);
+
+    let string_length = 4. / PI * radius;
+    settings.max_lines as f32 * string_length * density_per_length / (PI * radius * radius)
+}
@@ -0,0 +1,223 @@
+use crate::*;
+use bevy::render::render_resource::*;
+use bevy::ui::RelativeCursorPosition;
+use image::{GrayImage, Luma};
+use std::path::{Path, PathBuf};
+
+pub struct WeightMapPlugin;
+
+impl Plugin for WeightMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (load_weight_map, paint_weight_map, update_weight_overlay),
+        );
+    }
+}
+
+/// Importance of each pixel of the target image in the error, from black (ignored)
+/// to white (most important). Only relative weights matter.
+#[derive(Component, Default)]
+pub struct WeightMap {
+    /// Hand-made weights, framed like the target
+    pub image: Option<GrayImage>,
+    /// Hand-made weights aligned with the source image, loaded from a file or painted
+    pub source: Option<GrayImage>,
+    /// Weights computed from the content of the source image
+    pub automatic: Option<GrayImage>,
+    framing: Option<Framing>,
+    source_image_index: Option<usize>,
+}
+
+impl WeightMap {
+    /// Weights used by the solvers, preferring the hand-made ones.
+    pub fn active(&self) -> Option<&GrayImage> {
+        self.image.as_ref().or(self.automatic.as_ref())
+    }
+
+    fn reframe(&mut self, settings: &Settings) {
+        self.image = self
+            .source
+            .as_ref()
+            .map(|map| frame_image(map, settings, NEUTRAL_WEIGHT));
+        self.framing = Some(Framing::of(settings));
+    }
+}
+
+#[derive(Component)]
+struct WeightOverlay;
+
+/// Weight of the parts of the board without hand-made weights.
+const NEUTRAL_WEIGHT: u8 = 128;
+/// Radius of the brush, as a fraction of the width of the weight map.
+const BRUSH_RADIUS: f32 = 0.04;
+/// How much of the difference with the painted weight is covered on each frame.
+const BRUSH_STRENGTH: f32 = 0.2;
+
+/// Path of the hand-made weight map of a source image: `assets/a.jpg` uses `assets/a.weights.png`.
+pub fn weight_map_path(image_path: &str) -> PathBuf {
+    Path::new(image_path).with_extension("weights.png")
+}
+
+/// Spawns the overlay showing the weight map on top of the result image.
+pub fn spawn_weight_overlay(parent: &mut ChildBuilder, assets: &mut Assets<Image>) {
+    parent.spawn((
+        ImageBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                ..default()
+            },
+            image: UiImage::new(assets.add(overlay_image(&GrayImage::new(1, 1)))),
+            ..default()
+        },
+        WeightOverlay,
+    ));
+}
+
+/// Loads the weight map of the source image when another image is selected,
+/// and frames it again when the framing changes.
+fn load_weight_map(mut query: Query<(&mut WeightMap, &Settings), Changed<Settings>>) {
+    for (mut weight_map, settings) in &mut query {
+        if weight_map.source_image_index != Some(settings.source_image_index) {
+            let path = weight_map_path(IMAGES_PATHS[settings.source_image_index]);
+            let source = load_source_map(&path);
+            if source.is_some() {
+                info!("Loaded weight map {}", path.display());
+            }
+
+            weight_map.source = source;
+            weight_map.source_image_index = Some(settings.source_image_index);
+        } else if weight_map.framing == Some(Framing::of(settings)) {
+            continue;
+        }
+
+        weight_map.reframe(settings);
+    }
+}
+
+/// Weights aligned with the source image to start painting from: the automatic weights,
+/// or uniform, which weighs the error like no map at all.
+fn new_source_map(automatic: Option<&GrayImage>, settings: &Settings) -> Option<GrayImage> {
+    let size = image::image_dimensions(IMAGES_PATHS[settings.source_image_index]).ok()?;
+    Some(GrayImage::from_fn(size.0, size.1, |x, y| {
+        let point = (x as f32 + 0.5, y as f32 + 0.5);
+        let (frame_x, frame_y) = frame_position(point, size, settings);
+        match automatic {
+            Some(automatic)
+                if frame_x >= 0.
+                    && frame_y >= 0.
+                    && (frame_x as u32) < automatic.width()
+                    && (frame_y as u32) < automatic.height() =>
+            {
+                *automatic.get_pixel(frame_x as u32, frame_y as u32)
+            }
+            _ => Luma([NEUTRAL_WEIGHT]),
+        }
+    }))
+}
+
+/// While the overlay is shown, the left mouse button paints important areas
+/// on the result image and the right one paints areas to ignore.
+fn paint_weight_map(
+    mut query: Query<(
+        &mut WeightMap,
+        &RelativeCursorPosition,
+        &ViewSettings,
+        &Settings,
+    )>,
+    buttons: Res<Input<MouseButton>>,
+) {
+    for (mut weight_map, cursor, view_settings, settings) in &mut query {
+        let goal = if buttons.pressed(MouseButton::Left) {
+            255.
+        } else if buttons.pressed(MouseButton::Right) {
+            0.
+        } else {
+            continue;
+        };
+        let Some(position) = cursor.normalized else {
+            continue;
+        };
+        if !view_settings.show_weights || view_settings.framing || !cursor.mouse_over() {
+            continue;
+        }
+
+        // The source map is painted, so the painting follows the source image when reframed
+        if weight_map.source.is_none() {
+            weight_map.source = new_source_map(weight_map.automatic.as_ref(), settings);
+        }
+        let Some(source) = &mut weight_map.source else {
+            continue;
+        };
+
+        let size = settings.diameter as f32;
+        let frame_point = (position.x * size, position.y * size);
+        let (cx, cy) = source_position(frame_point, source.dimensions(), settings);
+        // The brush keeps its size on the frame
+        let radius =
+            BRUSH_RADIUS * source.width().min(source.height()) as f32 / settings.crop_zoom.max(0.1);
+
+        let min_x = (cx - radius).max(0.) as u32;
+        let min_y = (cy - radius).max(0.) as u32;
+        let max_x = ((cx + radius).ceil().max(0.) as u32).min(source.width());
+        let max_y = ((cy + radius).ceil().max(0.) as u32).min(source.height());
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let distance = f32::hypot(x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+                if distance < radius {
+                    let strength = BRUSH_STRENGTH * (1. - distance / radius);
+                    let pixel = source.get_pixel_mut(x, y);
+                    let value = pixel.0[0] as f32;
+                    pixel.0[0] = (value + (goal - value) * strength).round() as u8;
+                }
+            }
+        }
+
+        weight_map.reframe(settings);
+    }
+}
+
+/// Orange tint, more opaque on the most important pixels.
+fn overlay_image(weights: &GrayImage) -> Image {
+    let data = weights
+        .pixels()
+        .flat_map(|pixel| [255, 140, 0, (pixel.0[0] as f32 * 0.7) as u8])
+        .collect();
+    Image::new(
+        Extent3d {
+            width: weights.width(),
+            height: weights.height(),
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+fn update_weight_overlay(
+    trace_query: Query<(Ref<WeightMap>, Ref<ViewSettings>)>,
+    mut overlay_query: Query<(&mut Style, &UiImage), With<WeightOverlay>>,
+    mut assets: ResMut<Assets<Image>>,
+) {
+    let Ok((weight_map, view_settings)) = trace_query.get_single() else {
+        return;
+    };
+    if !weight_map.is_changed() && !view_settings.is_changed() {
+        return;
+    }
+
+    for (mut style, image) in &mut overlay_query {
+        let shown = view_settings.show_weights && weight_map.active().is_some();
+        style.display = if shown { Display::Flex } else { Display::None };
+
+        if let (true, Some(weights)) = (shown, weight_map.active()) {
+            if let Some(image) = assets.get_mut(&image.texture) {
+                *image = overlay_image(weights);
+            }
+        }
+    }
+}
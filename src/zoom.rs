@@ -0,0 +1,184 @@
+use crate::*;
+use bevy::input::mouse::MouseWheel;
+use bevy::ui::RelativeCursorPosition;
+use bevy::window::PrimaryWindow;
+
+pub struct ZoomPlugin;
+
+impl Plugin for ZoomPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (zoom_with_mouse, zoom_on_key, update_result_view));
+    }
+}
+
+/// Zoom factor of one step of the mouse wheel.
+const ZOOM_STEP: f32 = 1.2;
+const MAX_ZOOM: f32 = 32.;
+
+/// Part of the result image shown in the viewport that clips it.
+#[derive(Component)]
+pub struct ResultView {
+    /// Size of the image relative to the viewport
+    zoom: f32,
+    /// Position of the top left corner of the image, as a fraction of the viewport
+    offset: Vec2,
+}
+
+impl Default for ResultView {
+    fn default() -> Self {
+        Self {
+            zoom: 1.,
+            offset: Vec2::ZERO,
+        }
+    }
+}
+
+impl ResultView {
+    /// Zooms to `zoom` keeping the point of the image under `cursor` in place.
+    fn zoom_at(&mut self, zoom: f32, cursor: Vec2) {
+        let image_point = (cursor - self.offset) / self.zoom;
+        self.zoom = zoom.clamp(0.1, MAX_ZOOM);
+        self.offset = cursor - image_point * self.zoom;
+        self.clamp_offset();
+    }
+
+    /// Places an image in its viewport.
+    fn apply(&self, mut style: Mut<Style>) {
+        style.width = Val::Percent(self.zoom * 100.);
+        style.height = Val::Percent(self.zoom * 100.);
+        style.left = Val::Percent(self.offset.x * 100.);
+        style.top = Val::Percent(self.offset.y * 100.);
+    }
+
+    /// Keeps the viewport covered by the image, or the image centered when it is smaller.
+    fn clamp_offset(&mut self) {
+        self.offset = if self.zoom >= 1. {
+            self.offset.clamp(Vec2::splat(1. - self.zoom), Vec2::ZERO)
+        } else {
+            Vec2::splat((1. - self.zoom) / 2.)
+        };
+    }
+}
+
+/// Image outside the result viewport, such as the side-by-side target,
+/// zoomed and panned with the result image.
+#[derive(Component)]
+pub struct FollowsResultView;
+
+/// Square node clipping the result image and its overlays.
+pub fn result_viewport() -> impl Bundle {
+    (
+        NodeBundle {
+            style: Style {
+                width: Val::Auto,
+                height: Val::Percent(100.),
+                aspect_ratio: Some(1.),
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            ..default()
+        },
+        ResultView::default(),
+        RelativeCursorPosition::default(),
+    )
+}
+
+/// Style of the result image inside its viewport.
+pub fn result_style() -> Style {
+    Style {
+        position_type: PositionType::Absolute,
+        width: Val::Percent(100.),
+        height: Val::Percent(100.),
+        ..default()
+    }
+}
+
+/// The mouse wheel zooms on the cursor, unless it frames the source image.
+/// Dragging with the middle button pans the view, as does the left one
+/// when it does not frame the source image or paint the weights.
+fn zoom_with_mouse(
+    mut view_query: Query<(&mut ResultView, &RelativeCursorPosition)>,
+    trace_query: Query<&ViewSettings>,
+    buttons: Res<Input<MouseButton>>,
+    mut wheel: EventReader<MouseWheel>,
+    mut last_cursor: Local<Option<Vec2>>,
+) {
+    let scroll: f32 = wheel.iter().map(|event| event.y.signum()).sum();
+    let Ok(view_settings) = trace_query.get_single() else {
+        return;
+    };
+    let left_pans = !view_settings.framing && !view_settings.show_weights;
+
+    for (mut view, cursor) in &mut view_query {
+        let dragging = buttons.pressed(MouseButton::Middle)
+            || (left_pans && buttons.pressed(MouseButton::Left));
+        let drag_cursor = cursor
+            .normalized
+            .filter(|_| dragging && cursor.mouse_over());
+        let previous_cursor = std::mem::replace(&mut *last_cursor, drag_cursor);
+
+        let Some(position) = cursor.normalized.filter(|_| cursor.mouse_over()) else {
+            continue;
+        };
+
+        if scroll != 0. && !view_settings.framing {
+            let zoom = view.zoom * ZOOM_STEP.powf(scroll);
+            view.zoom_at(zoom, position);
+        }
+
+        if let (Some(cursor), Some(previous_cursor)) = (drag_cursor, previous_cursor) {
+            if cursor != previous_cursor {
+                view.offset += cursor - previous_cursor;
+                view.clamp_offset();
+            }
+        }
+    }
+}
+
+/// 0: fits the result image in the view
+/// 1: shows one pixel of the result image per pixel of the screen, around the center
+fn zoom_on_key(
+    mut view_query: Query<(&mut ResultView, &Node)>,
+    trace_query: Query<&Settings>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    keys: Res<Input<KeyCode>>,
+) {
+    let Ok(settings) = trace_query.get_single() else {
+        return;
+    };
+    let scale_factor = window_query
+        .get_single()
+        .map_or(1., |window| window.scale_factor() as f32);
+
+    for (mut view, node) in &mut view_query {
+        if keys.just_pressed(KeyCode::Key0) {
+            *view = ResultView::default();
+        }
+        if keys.just_pressed(KeyCode::Key1) {
+            let viewport_pixels = node.size().x * scale_factor;
+            if viewport_pixels > 0. {
+                let zoom = settings.diameter as f32 / viewport_pixels;
+                view.zoom_at(zoom, Vec2::splat(0.5));
+            }
+        }
+    }
+}
+
+fn update_result_view(
+    view_query: Query<(Ref<ResultView>, &Children)>,
+    mut style_query: Query<&mut Style, With<StringTrace>>,
+    mut follower_query: Query<&mut Style, (With<FollowsResultView>, Without<StringTrace>)>,
+) {
+    for (view, children) in &view_query {
+        if !view.is_changed() {
+            continue;
+        }
+        let mut styles = style_query.iter_many_mut(children);
+        while let Some(style) = styles.fetch_next() {
+            view.apply(style);
+        }
+        for style in &mut follower_query {
+            view.apply(style);
+        }
+    }
+}